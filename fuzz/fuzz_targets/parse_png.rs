@@ -0,0 +1,20 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pngme::{Chunk, ChunkReader, Ihdr, ImageData, Png};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(png) = Png::try_from(data) {
+        assert_eq!(png.as_bytes(), data);
+    }
+    // The first 13 bytes as IHDR, the rest as the zlib stream of IDAT.
+    if let Some((header, compressed)) = data.split_first_chunk::<13>() {
+        if let Ok(ihdr) = Ihdr::try_from(header.as_slice()) {
+            let _ = ImageData::from_zlib(ihdr, compressed);
+        }
+    }
+    let _ = Chunk::try_from(data);
+    if let Ok(reader) = ChunkReader::new(data) {
+        for _ in reader {}
+    }
+});
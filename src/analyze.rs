@@ -0,0 +1,503 @@
+use std::fmt::Display;
+
+use crate::{
+    chunk::Chunk, chunk_type::ChunkType, ihdr::ColorType, image::ImageData, png::Png, Result,
+};
+
+// Ancillary chunks larger than this are unusual enough to point out.
+const OVERSIZED_CHUNK_LEN: usize = 64 * 1024;
+
+// Pairs of values counted less often than this are left out of the
+// chi-square test, which is unreliable for small expected counts.
+const MIN_EXPECTED_COUNT: f64 = 5.0;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Anomaly {
+    PrivateChunk {
+        index: usize,
+        chunk_type: ChunkType,
+        length: usize,
+    },
+    OversizedChunk {
+        index: usize,
+        chunk_type: ChunkType,
+        length: usize,
+    },
+    DataAfterIend {
+        length: usize,
+    },
+}
+
+impl Display for Anomaly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Anomaly::PrivateChunk {
+                index,
+                chunk_type,
+                length,
+            } => write!(
+                f,
+                "Private chunk {} at index {} holds {} bytes",
+                chunk_type, index, length
+            ),
+            Anomaly::OversizedChunk {
+                index,
+                chunk_type,
+                length,
+            } => write!(
+                f,
+                "Ancillary chunk {} at index {} is unusually large ({} bytes)",
+                chunk_type, index, length
+            ),
+            Anomaly::DataAfterIend { length } => {
+                write!(f, "{} bytes of data follow IEND", length)
+            }
+        }
+    }
+}
+
+/// Results of the LSB tests on one channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelAnalysis {
+    pub channel: usize,
+    /// Probability that the pairs of values differing only in their LSB are
+    /// as evenly matched as LSB embedding makes them. Close to 1 suggests
+    /// embedding. `None` if the channel has too few distinct values.
+    pub chi_square_p: Option<f64>,
+    /// Fraction of samples estimated by RS analysis to carry embedded bits.
+    pub rs_rate: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub anomalies: Vec<Anomaly>,
+    pub channels: Vec<ChannelAnalysis>,
+    /// Why the pixels were not analysed, if they were not.
+    pub skipped: Option<String>,
+}
+
+impl Report {
+    /// The RS estimate averaged over every analysed channel.
+    pub fn estimated_rate(&self) -> Option<f64> {
+        match self.channels.len() {
+            0 => None,
+            len => Some(self.channels.iter().map(|c| c.rs_rate).sum::<f64>() / len as f64),
+        }
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Chunk anomalies:")?;
+        if self.anomalies.is_empty() {
+            write!(f, "\n  none")?;
+        }
+        for anomaly in &self.anomalies {
+            write!(f, "\n  {}", anomaly)?;
+        }
+        write!(f, "\nPixel analysis:")?;
+        if let Some(reason) = &self.skipped {
+            write!(f, "\n  skipped: {}", reason)?;
+        }
+        for channel in &self.channels {
+            let p = match channel.chi_square_p {
+                Some(p) => format!("{:.3}", p),
+                None => "n/a".into(),
+            };
+            write!(
+                f,
+                "\n  channel {}: chi-square p = {}, RS estimate = {:.1}%",
+                channel.channel,
+                p,
+                channel.rs_rate * 100.0
+            )?;
+        }
+        if let Some(rate) = self.estimated_rate() {
+            write!(f, "\nEstimated embedding rate: {:.1}%", rate * 100.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// Looks for signs of hidden data in a PNG file: suspicious chunks and
+/// statistical traces of LSB embedding in the pixels.
+pub fn analyze(bytes: &[u8]) -> Result<Report> {
+    let png = Png::try_from(bytes)?;
+    let mut anomalies = chunk_anomalies(png.chunks());
+    if !png.trailer().is_empty() {
+        anomalies.push(Anomaly::DataAfterIend {
+            length: png.trailer().len(),
+        });
+    }
+
+    let (channels, skipped) = match ImageData::decode(&png) {
+        Ok(image) => match analyzable(&image) {
+            Ok(()) => (analyze_pixels(&image), None),
+            Err(reason) => (Vec::new(), Some(reason)),
+        },
+        Err(error) => (Vec::new(), Some(error.to_string())),
+    };
+    Ok(Report {
+        anomalies,
+        channels,
+        skipped,
+    })
+}
+
+pub fn chunk_anomalies(chunks: &[Chunk]) -> Vec<Anomaly> {
+    let mut anomalies = Vec::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let chunk_type = chunk.chunk_type().clone();
+        let length = chunk.data().len();
+        if !chunk_type.is_public() {
+            anomalies.push(Anomaly::PrivateChunk {
+                index,
+                chunk_type: chunk_type.clone(),
+                length,
+            });
+        }
+        if !chunk_type.is_critical() && length > OVERSIZED_CHUNK_LEN {
+            anomalies.push(Anomaly::OversizedChunk {
+                index,
+                chunk_type: chunk_type.clone(),
+                length,
+            });
+        }
+    }
+    anomalies
+}
+
+fn analyzable(image: &ImageData) -> std::result::Result<(), String> {
+    let ihdr = image.ihdr();
+    if ihdr.color_type == ColorType::Indexed {
+        return Err("indexed images are not analysed".into());
+    }
+    if ihdr.bit_depth < 8 {
+        return Err(format!("{}-bit samples are not analysed", ihdr.bit_depth));
+    }
+    Ok(())
+}
+
+// Runs both tests on every channel. For 16-bit images only the low byte of
+// each sample is looked at, since that is where embedding happens.
+fn analyze_pixels(image: &ImageData) -> Vec<ChannelAnalysis> {
+    let channels = image.ihdr().color_type.channels();
+    let width = image.width() as usize;
+    let bytes_per_sample = image.bytes_per_sample();
+    (0..channels)
+        .map(|channel| {
+            let plane: Vec<u8> = image
+                .data()
+                .chunks(bytes_per_sample)
+                .skip(channel)
+                .step_by(channels)
+                .map(|sample| sample[bytes_per_sample - 1])
+                .collect();
+            ChannelAnalysis {
+                channel,
+                chi_square_p: chi_square(&plane),
+                rs_rate: rs_analysis(&plane, width),
+            }
+        })
+        .collect()
+}
+
+/// The chi-square attack of Westfeld and Pfitzmann. LSB embedding evens out
+/// the counts of each pair of values 2k and 2k+1; this returns the
+/// probability of seeing pairs at least as even as `samples` has.
+pub fn chi_square(samples: &[u8]) -> Option<f64> {
+    let mut histogram = [0u64; 256];
+    for &sample in samples {
+        histogram[sample as usize] += 1;
+    }
+    let mut statistic = 0.0;
+    let mut pairs = 0;
+    for pair in histogram.chunks(2) {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        if expected < MIN_EXPECTED_COUNT {
+            continue;
+        }
+        statistic += (pair[0] as f64 - expected).powi(2) / expected;
+        pairs += 1;
+    }
+    if pairs < 2 {
+        return None;
+    }
+    Some(upper_incomplete_gamma(
+        (pairs - 1) as f64 / 2.0,
+        statistic / 2.0,
+    ))
+}
+
+fn ln_gamma(x: f64) -> f64 {
+    // Lanczos approximation with g = 7.
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let sum = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |sum, (i, c)| {
+            sum + c / (x + i as f64 + 1.0)
+        });
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+// The regularized upper incomplete gamma function Q(a, x), which is also the
+// chi-square survival function for 2a degrees of freedom at 2x.
+fn upper_incomplete_gamma(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let prefix = (-x + a * x.ln() - ln_gamma(a)).exp();
+    if x < a + 1.0 {
+        // Series for the lower function P(a, x).
+        let (mut term, mut sum, mut n) = (1.0 / a, 1.0 / a, a);
+        for _ in 0..1000 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+            if term.abs() < sum.abs() * 1e-15 {
+                break;
+            }
+        }
+        return (1.0 - sum * prefix).clamp(0.0, 1.0);
+    }
+    // Continued fraction for Q(a, x), evaluated with Lentz's method.
+    let tiny = 1e-300;
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / tiny;
+    let mut d = 1.0 / b;
+    let mut h = d;
+    for i in 1..1000 {
+        let an = -(i as f64) * (i as f64 - a);
+        b += 2.0;
+        d = an * d + b;
+        if d.abs() < tiny {
+            d = tiny;
+        }
+        c = b + an / c;
+        if c.abs() < tiny {
+            c = tiny;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < 1e-15 {
+            break;
+        }
+    }
+    (prefix * h).clamp(0.0, 1.0)
+}
+
+// Fractions of regular and singular groups under the mask and its negation:
+// (R_M, S_M, R_-M, S_-M).
+fn rs_counts(plane: &[i32], width: usize) -> (f64, f64, f64, f64) {
+    const MASK: [i32; 4] = [0, 1, 1, 0];
+    let flip = |value: i32, direction: i32| match direction {
+        1 => value ^ 1,
+        -1 => ((value + 1) ^ 1) - 1,
+        _ => value,
+    };
+    let smoothness =
+        |group: &[i32]| -> i32 { group.windows(2).map(|pair| (pair[1] - pair[0]).abs()).sum() };
+    let mut counts = [0usize; 4];
+    let mut groups = 0;
+    for row in plane.chunks(width) {
+        for group in row.chunks_exact(MASK.len()) {
+            groups += 1;
+            let original = smoothness(group);
+            for (sign, (regular, singular)) in [(1, (0, 1)), (-1, (2, 3))] {
+                let flipped: Vec<i32> = group
+                    .iter()
+                    .zip(MASK)
+                    .map(|(&value, mask)| flip(value, mask * sign))
+                    .collect();
+                let changed = smoothness(&flipped);
+                if changed > original {
+                    counts[regular] += 1;
+                } else if changed < original {
+                    counts[singular] += 1;
+                }
+            }
+        }
+    }
+    let groups = groups.max(1) as f64;
+    (
+        counts[0] as f64 / groups,
+        counts[1] as f64 / groups,
+        counts[2] as f64 / groups,
+        counts[3] as f64 / groups,
+    )
+}
+
+/// RS analysis as described by Fridrich, Goljan and Du. Returns the estimated
+/// fraction of samples whose LSB carries embedded data, from 0 to 1.
+pub fn rs_analysis(samples: &[u8], width: usize) -> f64 {
+    let plane: Vec<i32> = samples.iter().map(|&sample| sample as i32).collect();
+    let flipped: Vec<i32> = plane.iter().map(|&sample| sample ^ 1).collect();
+    let (r_m, s_m, r_neg, s_neg) = rs_counts(&plane, width);
+    let (r_m1, s_m1, r_neg1, s_neg1) = rs_counts(&flipped, width);
+
+    let d0 = r_m - s_m;
+    let d1 = r_m1 - s_m1;
+    let d_neg0 = r_neg - s_neg;
+    let d_neg1 = r_neg1 - s_neg1;
+    let a = 2.0 * (d1 + d0);
+    let b = d_neg0 - d_neg1 - d1 - 3.0 * d0;
+    let c = d0 - d_neg0;
+
+    let x = if a.abs() < 1e-12 {
+        if b.abs() < 1e-12 {
+            return 0.0;
+        }
+        -c / b
+    } else {
+        // Noise can push the discriminant below zero when nearly every sample
+        // carries data; the closest real root is then the vertex.
+        let root = (b * b - 4.0 * a * c).max(0.0).sqrt();
+        let (x1, x2) = ((-b + root) / (2.0 * a), (-b - root) / (2.0 * a));
+        if x1.abs() <= x2.abs() {
+            x1
+        } else {
+            x2
+        }
+    };
+    (x / (x - 0.5)).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ihdr::{Ihdr, InterlaceMethod},
+        image::EncodingOptions,
+        stego::{self, StegoOptions},
+    };
+
+    // A smooth grayscale image with a little noise, standing in for a photo.
+    fn cover() -> ImageData {
+        let (width, height) = (256u32, 256u32);
+        let mut state = 12345u32;
+        let data = (0..width * height)
+            .map(|i| {
+                let (x, y) = ((i % width) as f64, (i / width) as f64);
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let noise = ((state >> 16) % 5) as f64 - 2.0;
+                let value = 128.0 + 60.0 * (x / 17.0).sin() * (y / 23.0).cos() + noise;
+                value.round().clamp(0.0, 255.0) as u8
+            })
+            .collect();
+        let ihdr = Ihdr {
+            width,
+            height,
+            bit_depth: 8,
+            color_type: ColorType::Grayscale,
+            compression_method: 0,
+            filter_method: 0,
+            interlace_method: InterlaceMethod::None,
+        };
+        ImageData::new(ihdr, data).unwrap()
+    }
+
+    fn embed_fraction(image: &ImageData, fraction: f64) -> ImageData {
+        let mut image = image.clone();
+        let options = StegoOptions::default();
+        let capacity = stego::capacity(image.ihdr(), &options).unwrap();
+        let mut state = 99u64;
+        let payload: Vec<u8> = (0..(capacity as f64 * fraction) as usize)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1);
+                (state >> 56) as u8
+            })
+            .collect();
+        stego::embed(&mut image, &payload, &options).unwrap();
+        image
+    }
+
+    #[test]
+    fn test_chi_square() {
+        let image = cover();
+        let clean = chi_square(image.data()).unwrap();
+        let full = chi_square(embed_fraction(&image, 1.0).data()).unwrap();
+        assert!(clean < 0.05, "{}", clean);
+        assert!(full > 0.5, "{}", full);
+        assert_eq!(chi_square(&[7; 100]), None);
+    }
+
+    #[test]
+    fn test_rs_analysis() {
+        let image = cover();
+        let clean = rs_analysis(image.data(), 256);
+        assert!(clean < 0.1, "{}", clean);
+        for fraction in [0.25, 0.5, 1.0] {
+            let rate = rs_analysis(embed_fraction(&image, fraction).data(), 256);
+            assert!((rate - fraction).abs() < 0.15, "{} for {}", rate, fraction);
+        }
+    }
+
+    #[test]
+    fn test_upper_incomplete_gamma() {
+        // Chi-square survival function values for 2 and 10 degrees of freedom.
+        assert!((upper_incomplete_gamma(1.0, 1.0) - (-1.0f64).exp()).abs() < 1e-12);
+        assert!((upper_incomplete_gamma(5.0, 9.1535 / 2.0) - 0.5173).abs() < 1e-3);
+        assert!((upper_incomplete_gamma(5.0, 18.307 / 2.0) - 0.05).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_chunk_anomalies() {
+        let mut png = cover().to_png(&EncodingOptions::default(), vec![]).unwrap();
+        // Appended chunks go before IEND.
+        let index = png.chunks().len() - 1;
+        png.append_chunk(Chunk::new(
+            "ruSt".parse().unwrap(),
+            b"Secret message".to_vec(),
+        ));
+        png.append_chunk(Chunk::new(ChunkType::TEXT, vec![b'a'; 70_000]));
+        let mut bytes = png.as_bytes();
+        bytes.extend_from_slice(b"trailing");
+
+        let report = analyze(&bytes).unwrap();
+        assert_eq!(
+            report.anomalies,
+            vec![
+                Anomaly::PrivateChunk {
+                    index,
+                    chunk_type: "ruSt".parse().unwrap(),
+                    length: 14
+                },
+                Anomaly::OversizedChunk {
+                    index: index + 1,
+                    chunk_type: ChunkType::TEXT,
+                    length: 70_000
+                },
+                Anomaly::DataAfterIend { length: 8 },
+            ]
+        );
+        assert_eq!(report.channels.len(), 1);
+        assert!(report.estimated_rate().unwrap() < 0.1);
+    }
+
+    #[test]
+    fn test_report_detects_embedding() {
+        let png = embed_fraction(&cover(), 1.0)
+            .to_png(&EncodingOptions::default(), vec![])
+            .unwrap();
+        let report = analyze(&png.as_bytes()).unwrap();
+        assert!(report.anomalies.is_empty());
+        assert!(report.estimated_rate().unwrap() > 0.8);
+        assert!(report.to_string().contains("Estimated embedding rate"));
+    }
+}
@@ -0,0 +1,529 @@
+use std::fmt::Display;
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, error::PngError, png::Png, Result};
+
+fn invalid(reason: String) -> PngError {
+    PngError::InvalidAnimation { reason }
+}
+
+// Chunks that describe how to show every frame, so each extracted frame
+// keeps them. Anything else, such as text or hidden payloads, stays behind.
+const FRAME_WIDE_CHUNKS: [&[u8; 4]; 8] = [
+    b"PLTE", b"tRNS", b"gAMA", b"cHRM", b"sRGB", b"iCCP", b"sBIT", b"pHYs",
+];
+
+/// Whether `chunk_type` is one of the chunks that make up an animation.
+pub fn is_animation_chunk(chunk_type: &ChunkType) -> bool {
+    [ChunkType::ACTL, ChunkType::FCTL, ChunkType::FDAT].contains(chunk_type)
+}
+
+/// The acTL chunk, which marks a PNG as animated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnimationControl {
+    pub num_frames: u32,
+    /// Times to play the animation, 0 meaning forever.
+    pub num_plays: u32,
+}
+
+impl TryFrom<&Chunk> for AnimationControl {
+    type Error = crate::Error;
+    fn try_from(value: &Chunk) -> Result<Self> {
+        let data = value.data();
+        if value.chunk_type() != &ChunkType::ACTL || data.len() != 8 {
+            return Err(invalid(format!(
+                "expected an 8 byte acTL chunk but found {} with {} bytes",
+                value.chunk_type(),
+                data.len()
+            )));
+        }
+        Ok(AnimationControl {
+            num_frames: u32::from_be_bytes(data[0..4].try_into().unwrap()),
+            num_plays: u32::from_be_bytes(data[4..8].try_into().unwrap()),
+        })
+    }
+}
+
+impl AnimationControl {
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = self.num_frames.to_be_bytes().to_vec();
+        data.extend_from_slice(&self.num_plays.to_be_bytes());
+        Chunk::new(ChunkType::ACTL, data)
+    }
+}
+
+/// What happens to a frame's region before the next frame is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisposeOp {
+    None,
+    Background,
+    Previous,
+}
+
+/// How a frame is drawn over what is already there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendOp {
+    Source,
+    Over,
+}
+
+impl Display for DisposeOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DisposeOp::None => write!(f, "none"),
+            DisposeOp::Background => write!(f, "background"),
+            DisposeOp::Previous => write!(f, "previous"),
+        }
+    }
+}
+
+impl Display for BlendOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlendOp::Source => write!(f, "source"),
+            BlendOp::Over => write!(f, "over"),
+        }
+    }
+}
+
+/// The fcTL chunk describing one frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameControl {
+    pub sequence_number: u32,
+    pub width: u32,
+    pub height: u32,
+    pub x_offset: u32,
+    pub y_offset: u32,
+    pub delay_num: u16,
+    pub delay_den: u16,
+    pub dispose_op: DisposeOp,
+    pub blend_op: BlendOp,
+}
+
+impl TryFrom<&Chunk> for FrameControl {
+    type Error = crate::Error;
+    fn try_from(value: &Chunk) -> Result<Self> {
+        let data = value.data();
+        if value.chunk_type() != &ChunkType::FCTL || data.len() != 26 {
+            return Err(invalid(format!(
+                "expected a 26 byte fcTL chunk but found {} with {} bytes",
+                value.chunk_type(),
+                data.len()
+            )));
+        }
+        let u32_at = |index: usize| u32::from_be_bytes(data[index..index + 4].try_into().unwrap());
+        let u16_at = |index: usize| u16::from_be_bytes(data[index..index + 2].try_into().unwrap());
+        let dispose_op = match data[24] {
+            0 => DisposeOp::None,
+            1 => DisposeOp::Background,
+            2 => DisposeOp::Previous,
+            op => return Err(invalid(format!("unknown dispose op {}", op))),
+        };
+        let blend_op = match data[25] {
+            0 => BlendOp::Source,
+            1 => BlendOp::Over,
+            op => return Err(invalid(format!("unknown blend op {}", op))),
+        };
+        Ok(FrameControl {
+            sequence_number: u32_at(0),
+            width: u32_at(4),
+            height: u32_at(8),
+            x_offset: u32_at(12),
+            y_offset: u32_at(16),
+            delay_num: u16_at(20),
+            delay_den: u16_at(22),
+            dispose_op,
+            blend_op,
+        })
+    }
+}
+
+impl FrameControl {
+    /// How long the frame is shown, in seconds.
+    pub fn delay(&self) -> f64 {
+        // A denominator of 0 means hundredths of a second.
+        let den = match self.delay_den {
+            0 => 100,
+            den => den,
+        };
+        self.delay_num as f64 / den as f64
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = Vec::with_capacity(26);
+        for value in [
+            self.sequence_number,
+            self.width,
+            self.height,
+            self.x_offset,
+            self.y_offset,
+        ] {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        data.extend_from_slice(&self.delay_num.to_be_bytes());
+        data.extend_from_slice(&self.delay_den.to_be_bytes());
+        data.push(match self.dispose_op {
+            DisposeOp::None => 0,
+            DisposeOp::Background => 1,
+            DisposeOp::Previous => 2,
+        });
+        data.push(match self.blend_op {
+            BlendOp::Source => 0,
+            BlendOp::Over => 1,
+        });
+        Chunk::new(ChunkType::FCTL, data)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub control: FrameControl,
+    /// Whether the frame is the IDAT image rather than made of fdAT chunks.
+    pub is_default_image: bool,
+    /// The frame's zlib stream, split the way it was across chunks.
+    pub data: Vec<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Animation {
+    pub control: AnimationControl,
+    pub frames: Vec<Frame>,
+}
+
+impl Animation {
+    /// Reads the animation out of `png`, checking that fcTL and fdAT
+    /// sequence numbers run from 0 without gaps and that every frame fits
+    /// in the image.
+    pub fn parse(png: &Png) -> Result<Animation> {
+        let ihdr = png.header_info()?;
+        let mut control = None;
+        let mut frames: Vec<Frame> = Vec::new();
+        let mut next_sequence_number = 0;
+        let mut check_sequence = |sequence_number: u32| match sequence_number {
+            n if n == next_sequence_number => {
+                next_sequence_number += 1;
+                Ok(())
+            }
+            n => Err(invalid(format!(
+                "expected sequence number {} but found {}",
+                next_sequence_number, n
+            ))),
+        };
+        let mut seen_idat = false;
+        for chunk in png.chunks() {
+            let chunk_type = chunk.chunk_type();
+            if chunk_type == &ChunkType::ACTL {
+                if control.is_some() || seen_idat {
+                    return Err(invalid("acTL must appear once, before IDAT".into()));
+                }
+                control = Some(AnimationControl::try_from(chunk)?);
+            } else if chunk_type == &ChunkType::FCTL {
+                let frame_control = FrameControl::try_from(chunk)?;
+                check_sequence(frame_control.sequence_number)?;
+                check_region(&frame_control, ihdr.width, ihdr.height, !seen_idat)?;
+                frames.push(Frame {
+                    control: frame_control,
+                    is_default_image: !seen_idat,
+                    data: Vec::new(),
+                });
+            } else if chunk_type == &ChunkType::IDAT {
+                seen_idat = true;
+                if let Some(frame) = frames.last_mut().filter(|frame| frame.is_default_image) {
+                    frame.data.push(chunk.data().to_vec());
+                }
+            } else if chunk_type == &ChunkType::FDAT {
+                if chunk.data().len() < 4 {
+                    return Err(invalid("fdAT chunk is too short".into()));
+                }
+                check_sequence(u32::from_be_bytes(chunk.data()[0..4].try_into().unwrap()))?;
+                match frames.last_mut().filter(|frame| !frame.is_default_image) {
+                    Some(frame) => frame.data.push(chunk.data()[4..].to_vec()),
+                    None => return Err(invalid("fdAT chunk does not follow an fcTL".into())),
+                }
+            }
+        }
+        let control = control.ok_or(PngError::ChunkNotFound {
+            chunk_type: ChunkType::ACTL,
+        })?;
+        if let Some(index) = frames.iter().position(|frame| frame.data.is_empty()) {
+            return Err(invalid(format!("frame {} has no image data", index)));
+        }
+        if frames.is_empty() || frames.len() != control.num_frames as usize {
+            return Err(invalid(format!(
+                "acTL declares {} frames but {} were found",
+                control.num_frames,
+                frames.len()
+            )));
+        }
+        Ok(Animation { control, frames })
+    }
+}
+
+fn check_region(
+    control: &FrameControl,
+    width: u32,
+    height: u32,
+    is_default_image: bool,
+) -> Result<()> {
+    let fits = control.width > 0
+        && control.height > 0
+        && control.x_offset as u64 + control.width as u64 <= width as u64
+        && control.y_offset as u64 + control.height as u64 <= height as u64;
+    if !fits {
+        return Err(invalid(format!(
+            "frame {}x{} at ({}, {}) does not fit in the {}x{} image",
+            control.width, control.height, control.x_offset, control.y_offset, width, height
+        )));
+    }
+    let covers_image = control.width == width
+        && control.height == height
+        && control.x_offset == 0
+        && control.y_offset == 0;
+    if is_default_image && !covers_image {
+        return Err(invalid(
+            "a frame made of the IDAT image must cover the whole image".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Builds a standalone PNG from frame `index` of `png`. The frame is taken
+/// as stored, so it is not composited over the frames before it.
+pub fn extract_frame(png: &Png, index: usize) -> Result<Png> {
+    let animation = Animation::parse(png)?;
+    let frame = animation.frames.get(index).ok_or(PngError::FrameNotFound {
+        index,
+        frames: animation.frames.len(),
+    })?;
+    let mut ihdr = png.header_info()?;
+    ihdr.width = frame.control.width;
+    ihdr.height = frame.control.height;
+    let mut chunks = vec![ihdr.to_chunk()];
+    chunks.extend(
+        png.chunks()
+            .iter()
+            .filter(|chunk| {
+                FRAME_WIDE_CHUNKS
+                    .iter()
+                    .any(|chunk_type| chunk.chunk_type().bytes() == **chunk_type)
+            })
+            .cloned(),
+    );
+    chunks.extend(
+        frame
+            .data
+            .iter()
+            .map(|data| Chunk::new(ChunkType::IDAT, data.clone())),
+    );
+    chunks.push(Chunk::new(ChunkType::IEND, Vec::new()));
+    Ok(Png::from_chunks(chunks))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ihdr::{ColorType, Ihdr, InterlaceMethod},
+        image::{EncodingOptions, ImageData},
+        png::ChunkPosition,
+    };
+
+    fn image(width: u32, height: u32, seed: u8) -> ImageData {
+        let ihdr = Ihdr {
+            width,
+            height,
+            bit_depth: 8,
+            color_type: ColorType::Rgb,
+            compression_method: 0,
+            filter_method: 0,
+            interlace_method: InterlaceMethod::None,
+        };
+        let data = (0..width * height * 3)
+            .map(|i| (i as u8).wrapping_mul(seed))
+            .collect();
+        ImageData::new(ihdr, data).unwrap()
+    }
+
+    fn frame_control(sequence_number: u32, width: u32, height: u32, offset: u32) -> FrameControl {
+        FrameControl {
+            sequence_number,
+            width,
+            height,
+            x_offset: offset,
+            y_offset: offset,
+            delay_num: 1,
+            delay_den: 10,
+            dispose_op: DisposeOp::None,
+            blend_op: BlendOp::Source,
+        }
+    }
+
+    // Three frames: the 8x8 IDAT image, a 4x4 frame at (2, 2) and another
+    // full size frame, the last two split across two fdAT chunks each.
+    fn animation() -> Png {
+        let options = EncodingOptions::default();
+        let mut chunks = vec![
+            image(8, 8, 3).ihdr().to_chunk(),
+            AnimationControl {
+                num_frames: 3,
+                num_plays: 0,
+            }
+            .to_chunk(),
+            frame_control(0, 8, 8, 0).to_chunk(),
+        ];
+        chunks.extend(image(8, 8, 3).encode(&options).unwrap());
+        let mut sequence_number = 1;
+        for (width, offset, seed) in [(4, 2, 5), (8, 0, 7)] {
+            chunks.push(frame_control(sequence_number, width, width, offset).to_chunk());
+            sequence_number += 1;
+            let stream: Vec<u8> = image(width, width, seed)
+                .encode(&options)
+                .unwrap()
+                .iter()
+                .flat_map(|chunk| chunk.data().to_vec())
+                .collect();
+            let (first, second) = stream.split_at(stream.len() / 2);
+            for part in [first, second] {
+                let mut data = sequence_number.to_be_bytes().to_vec();
+                data.extend_from_slice(part);
+                chunks.push(Chunk::new(ChunkType::FDAT, data));
+                sequence_number += 1;
+            }
+        }
+        chunks.push(Chunk::new(ChunkType::IEND, Vec::new()));
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_parse() {
+        let animation = Animation::parse(&animation()).unwrap();
+        assert_eq!(animation.control.num_plays, 0);
+        assert_eq!(animation.frames.len(), 3);
+        assert!(animation.frames[0].is_default_image);
+        assert!(!animation.frames[1].is_default_image);
+        assert_eq!(animation.frames[1].control, frame_control(1, 4, 4, 2));
+        assert_eq!(animation.frames[2].control.sequence_number, 4);
+        assert_eq!(animation.frames[2].data.len(), 2);
+        assert_eq!(animation.frames[0].control.delay(), 0.1);
+    }
+
+    #[test]
+    fn test_control_round_trips() {
+        let control = FrameControl {
+            dispose_op: DisposeOp::Previous,
+            blend_op: BlendOp::Over,
+            delay_den: 0,
+            ..frame_control(9, 3, 2, 1)
+        };
+        assert_eq!(
+            FrameControl::try_from(&control.to_chunk()).unwrap(),
+            control
+        );
+        assert_eq!(control.delay(), 0.01);
+        let control = AnimationControl {
+            num_frames: 4,
+            num_plays: 2,
+        };
+        assert_eq!(
+            AnimationControl::try_from(&control.to_chunk()).unwrap(),
+            control
+        );
+    }
+
+    #[test]
+    fn test_invalid_animations() {
+        let chunks = animation().chunks().to_vec();
+        let with = |index: usize, chunk: Option<Chunk>| {
+            let mut chunks = chunks.clone();
+            match chunk {
+                Some(chunk) => chunks[index] = chunk,
+                None => {
+                    chunks.remove(index);
+                }
+            }
+            Animation::parse(&Png::from_chunks(chunks))
+        };
+        let cases = [
+            // Sequence numbers out of order.
+            with(4, Some(frame_control(2, 4, 4, 2).to_chunk())),
+            // A missing fdAT leaves a gap.
+            with(5, None),
+            // Frame does not fit.
+            with(4, Some(frame_control(1, 4, 4, 6).to_chunk())),
+            // Frame count disagrees with acTL.
+            with(
+                1,
+                Some(
+                    AnimationControl {
+                        num_frames: 2,
+                        num_plays: 0,
+                    }
+                    .to_chunk(),
+                ),
+            ),
+        ];
+        for result in cases {
+            assert!(
+                matches!(result, Err(PngError::InvalidAnimation { .. })),
+                "{:?}",
+                result
+            );
+        }
+        assert!(matches!(with(1, None), Err(PngError::ChunkNotFound { .. })));
+    }
+
+    #[test]
+    fn test_extract_frame() {
+        let mut png = animation();
+        png.insert_chunk(
+            Chunk::new("gAMA".parse().unwrap(), 45455u32.to_be_bytes().to_vec()),
+            ChunkPosition::BeforeIdat,
+        );
+        png.insert_chunk(
+            Chunk::new("ruSt".parse().unwrap(), b"hidden".to_vec()),
+            ChunkPosition::BeforeIdat,
+        );
+        let frame = extract_frame(&png, 1).unwrap();
+        // Only chunks that apply to every frame are copied.
+        let types: Vec<String> = frame
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "gAMA", "IDAT", "IDAT", "IEND"]);
+        assert_eq!(frame.header_info().unwrap().width, 4);
+        assert_eq!(ImageData::decode(&frame).unwrap(), image(4, 4, 5));
+        let frame = extract_frame(&png, 0).unwrap();
+        assert_eq!(ImageData::decode(&frame).unwrap(), image(8, 8, 3));
+        assert!(matches!(
+            extract_frame(&png, 3),
+            Err(PngError::FrameNotFound {
+                index: 3,
+                frames: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn test_inserted_chunks_keep_animation() {
+        let original = Animation::parse(&animation()).unwrap();
+        for position in [
+            ChunkPosition::BeforeIdat,
+            ChunkPosition::AfterIdat,
+            ChunkPosition::BeforeIend,
+        ] {
+            let mut png = animation();
+            png.insert_chunk(
+                Chunk::new("ruSt".parse().unwrap(), b"hidden".to_vec()),
+                position,
+            );
+            assert_eq!(Animation::parse(&png).unwrap(), original);
+            // The fcTL of the IDAT frame must stay right before IDAT.
+            let idat = png
+                .chunks()
+                .iter()
+                .position(|chunk| chunk.chunk_type() == &ChunkType::IDAT)
+                .unwrap();
+            assert_eq!(png.chunks()[idat - 1].chunk_type(), &ChunkType::FCTL);
+        }
+    }
+}
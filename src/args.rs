@@ -1,11 +1,22 @@
 use std::path::PathBuf;
 
-use clap::{Parser, Subcommand};
+use clap::{Args as ClapArgs, Parser, Subcommand, ValueEnum};
+use pngme::{
+    commands::KeyKind,
+    compress::{Compression, DEFAULT_MAX_OUTPUT_SIZE},
+    output::OutputFormat,
+    stego::StegoOptions,
+    ChunkPosition,
+};
 
 #[derive(Parser)]
 pub struct Args {
     #[command(subcommand)]
     pub command: Commands,
+    /// Output format for print, list, decode, info, diff, repair and stego
+    /// extract
+    #[arg(long, global = true, value_enum, default_value_t = Format::Text)]
+    pub format: Format,
 }
 
 #[derive(Subcommand)]
@@ -13,12 +24,52 @@ pub enum Commands {
     Encode {
         file_path: PathBuf,
         chunk_type: String,
-        message: String,
+        /// The message to hide
+        #[arg(required_unless_present_any = ["file", "stdin"], conflicts_with_all = ["file", "stdin"])]
+        message: Option<String>,
         output_file: Option<PathBuf>,
+        /// Write the result here instead of over the input file
+        #[arg(short, long, value_name = "PATH", conflicts_with = "output_file")]
+        output: Option<PathBuf>,
+        /// Hide the contents of a file instead of a message
+        #[arg(long, value_name = "PATH", conflicts_with = "stdin")]
+        file: Option<PathBuf>,
+        /// Hide bytes read from standard input instead of a message
+        #[arg(long)]
+        stdin: bool,
+        #[arg(long, value_enum, default_value_t = Position::BeforeIend)]
+        position: Position,
+        #[arg(long, conflicts_with = "recipient")]
+        encrypt: bool,
+        #[arg(long, value_name = "PUBKEY_FILE")]
+        recipient: Option<PathBuf>,
+        #[arg(long, value_name = "KEY_FILE")]
+        sign: Option<PathBuf>,
+        #[arg(long, requires = "sign")]
+        sign_critical: bool,
+        /// Split the payload across chunks of at most this many bytes
+        #[arg(long, value_name = "BYTES")]
+        max_chunk_size: Option<usize>,
+        /// Compress the payload before hiding it
+        #[arg(long, value_enum, value_name = "METHOD", num_args = 0..=1, default_missing_value = "deflate")]
+        compress: Option<CompressionMethod>,
     },
     Decode {
         file_path: PathBuf,
         chunk_type: String,
+        #[arg(long, conflicts_with = "identity")]
+        decrypt: bool,
+        #[arg(long, value_name = "KEY_FILE")]
+        identity: Option<PathBuf>,
+        /// Write the decoded bytes to a file
+        #[arg(long, value_name = "PATH", conflicts_with = "raw")]
+        output: Option<PathBuf>,
+        /// Write the decoded bytes to standard output untouched
+        #[arg(long)]
+        raw: bool,
+        /// Refuse compressed payloads that inflate to more than this many bytes
+        #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_MAX_OUTPUT_SIZE)]
+        max_output_size: usize,
     },
     Remove {
         file_path: PathBuf,
@@ -27,4 +78,293 @@ pub enum Commands {
     Print {
         file_path: PathBuf,
     },
+    /// List every chunk with its offset, CRC and property bits
+    List {
+        file_path: PathBuf,
+        /// Dump each chunk's data as hex
+        #[arg(long)]
+        hexdump: bool,
+        /// Dump at most this many bytes of each chunk
+        #[arg(
+            long,
+            value_name = "BYTES",
+            default_value_t = 256,
+            requires = "hexdump"
+        )]
+        max_bytes: usize,
+    },
+    Check {
+        file_path: PathBuf,
+    },
+    Info {
+        file_path: PathBuf,
+    },
+    Keygen {
+        output_prefix: PathBuf,
+        #[arg(long, value_enum, default_value_t = KeyType::Recipient)]
+        kind: KeyType,
+    },
+    Verify {
+        file_path: PathBuf,
+        #[arg(long, value_name = "PUBKEY_FILE")]
+        key: PathBuf,
+    },
+    /// Manage tEXt, zTXt and iTXt metadata
+    Text {
+        #[command(subcommand)]
+        command: TextCommands,
+    },
+    /// Show which chunks differ between two files
+    Diff {
+        old_file: PathBuf,
+        new_file: PathBuf,
+    },
+    /// Look for signs of hidden data
+    Analyze {
+        file_path: PathBuf,
+    },
+    /// Hide payloads in the pixels instead of in chunks
+    Stego {
+        #[command(subcommand)]
+        command: StegoCommands,
+    },
+    /// Inspect and edit animated PNGs
+    Apng {
+        #[command(subcommand)]
+        command: ApngCommands,
+    },
+    /// Inspect or remove data appended after IEND
+    Trailer {
+        #[command(subcommand)]
+        command: TrailerCommands,
+    },
+    /// Fix chunks whose CRC does not match their data
+    Repair {
+        file_path: PathBuf,
+        output_file: Option<PathBuf>,
+        /// Flip back a single damaged bit where one explains the bad CRC,
+        /// instead of only recalculating it
+        #[arg(long)]
+        correct_bits: bool,
+    },
+}
+
+impl Commands {
+    /// Whether the command can print JSON or YAML.
+    pub fn supports_format(&self) -> bool {
+        matches!(
+            self,
+            Commands::Print { .. }
+                | Commands::List { .. }
+                | Commands::Decode { .. }
+                | Commands::Info { .. }
+                | Commands::Diff { .. }
+                | Commands::Repair { .. }
+                | Commands::Stego {
+                    command: StegoCommands::Extract { .. }
+                }
+        )
+    }
+}
+
+#[derive(Subcommand)]
+pub enum StegoCommands {
+    Embed {
+        file_path: PathBuf,
+        /// The message to hide
+        #[arg(required_unless_present_any = ["file", "stdin"], conflicts_with_all = ["file", "stdin"])]
+        message: Option<String>,
+        output_file: Option<PathBuf>,
+        /// Write the result here instead of over the input file
+        #[arg(short, long, value_name = "PATH", conflicts_with = "output_file")]
+        output: Option<PathBuf>,
+        #[arg(long, value_name = "PATH", conflicts_with = "stdin")]
+        file: Option<PathBuf>,
+        #[arg(long)]
+        stdin: bool,
+        #[arg(long, conflicts_with = "recipient")]
+        encrypt: bool,
+        #[arg(long, value_name = "PUBKEY_FILE")]
+        recipient: Option<PathBuf>,
+        #[arg(long, value_enum, value_name = "METHOD", num_args = 0..=1, default_missing_value = "deflate")]
+        compress: Option<CompressionMethod>,
+        #[command(flatten)]
+        stego: StegoArgs,
+    },
+    Extract {
+        file_path: PathBuf,
+        #[arg(long, conflicts_with = "identity")]
+        decrypt: bool,
+        #[arg(long, value_name = "KEY_FILE")]
+        identity: Option<PathBuf>,
+        #[arg(long, value_name = "PATH", conflicts_with = "raw")]
+        output: Option<PathBuf>,
+        #[arg(long)]
+        raw: bool,
+        #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_MAX_OUTPUT_SIZE)]
+        max_output_size: usize,
+        #[command(flatten)]
+        stego: StegoArgs,
+    },
+    /// Show how many bytes the image can hide
+    Capacity {
+        file_path: PathBuf,
+        #[command(flatten)]
+        stego: StegoArgs,
+    },
+}
+
+#[derive(ClapArgs)]
+pub struct StegoArgs {
+    /// Low bits of each sample to use
+    #[arg(long, default_value_t = 1)]
+    pub bits: u8,
+    /// Channels to use, by index; defaults to every channel but alpha
+    #[arg(long, value_delimiter = ',')]
+    pub channels: Vec<u8>,
+    /// Seeds the order in which pixels are used
+    #[arg(long, default_value = "")]
+    pub key: String,
+}
+
+impl From<StegoArgs> for StegoOptions {
+    fn from(args: StegoArgs) -> Self {
+        StegoOptions {
+            bits_per_channel: args.bits,
+            channel_mask: match args.channels.is_empty() {
+                true => None,
+                false => Some(args.channels.iter().fold(0, |mask, &channel| {
+                    mask | 1u8.checked_shl(channel.into()).unwrap_or(u8::MAX)
+                })),
+            },
+            key: args.key,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum ApngCommands {
+    /// List frames with their timing and placement
+    Frames { file_path: PathBuf },
+    /// Save one frame, as stored, as a standalone PNG
+    ExtractFrame {
+        file_path: PathBuf,
+        index: usize,
+        output: PathBuf,
+    },
+    /// Set how many times the animation plays; 0 loops forever
+    SetLoops { file_path: PathBuf, loops: u32 },
+}
+
+#[derive(Subcommand)]
+pub enum TrailerCommands {
+    Show {
+        file_path: PathBuf,
+    },
+    Extract {
+        file_path: PathBuf,
+        output: PathBuf,
+    },
+    Strip {
+        file_path: PathBuf,
+        output_file: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TextCommands {
+    List {
+        file_path: PathBuf,
+    },
+    Get {
+        file_path: PathBuf,
+        #[arg(long)]
+        keyword: String,
+    },
+    Set {
+        file_path: PathBuf,
+        #[arg(long)]
+        keyword: String,
+        text: String,
+        /// Store the text compressed, in zTXt or a compressed iTXt
+        #[arg(long)]
+        compress: bool,
+        /// Language of the text; implies iTXt
+        #[arg(long, value_name = "TAG")]
+        language: Option<String>,
+        /// The keyword translated into the text's language; implies iTXt
+        #[arg(long)]
+        translated_keyword: Option<String>,
+    },
+    Remove {
+        file_path: PathBuf,
+        #[arg(long)]
+        keyword: String,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Position {
+    BeforeIdat,
+    AfterIdat,
+    BeforeIend,
+}
+
+impl From<Position> for ChunkPosition {
+    fn from(position: Position) -> Self {
+        match position {
+            Position::BeforeIdat => ChunkPosition::BeforeIdat,
+            Position::AfterIdat => ChunkPosition::AfterIdat,
+            Position::BeforeIend => ChunkPosition::BeforeIend,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum KeyType {
+    Recipient,
+    Signing,
+}
+
+impl From<KeyType> for KeyKind {
+    fn from(key_type: KeyType) -> Self {
+        match key_type {
+            KeyType::Recipient => KeyKind::Recipient,
+            KeyType::Signing => KeyKind::Signing,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum CompressionMethod {
+    Deflate,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl From<CompressionMethod> for Compression {
+    fn from(method: CompressionMethod) -> Self {
+        match method {
+            CompressionMethod::Deflate => Compression::Deflate,
+            #[cfg(feature = "zstd")]
+            CompressionMethod::Zstd => Compression::Zstd,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Text,
+    Json,
+    Yaml,
+}
+
+impl From<Format> for OutputFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Text => OutputFormat::Text,
+            Format::Json => OutputFormat::Json,
+            Format::Yaml => OutputFormat::Yaml,
+        }
+    }
 }
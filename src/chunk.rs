@@ -1,8 +1,8 @@
-use std::{fmt::Display, io::Read};
+use std::{fmt::Display, io::Write};
 
 use crc::Crc;
 
-use crate::chunk_type::ChunkType;
+use crate::{chunk_type::ChunkType, error::PngError};
 
 #[derive(Debug, Clone)]
 pub struct Chunk {
@@ -12,43 +12,43 @@ pub struct Chunk {
     crc: u32,
 }
 
-fn calculate_crc(bytes: &[u8]) -> u32 {
-    let iso_hldc = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
-    match bytes.try_into() {
-        Ok(bytes) => iso_hldc.checksum(bytes),
-        Err(_) => unreachable!(),
-    }
+const ISO_HDLC: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+fn calculate_crc(chunk_type: &ChunkType, data: &[u8]) -> u32 {
+    let mut digest = ISO_HDLC.digest();
+    digest.update(&chunk_type.bytes());
+    digest.update(data);
+    digest.finalize()
 }
 
 impl TryFrom<&[u8]> for Chunk {
     type Error = crate::Error;
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        let length = match value[0..4].try_into() {
-            Ok(bytes) => u32::from_be_bytes(bytes),
-            Err(_) => return Err("Chunk length must be 4 bytes long".into()),
-        };
-        let chunk_type: ChunkType = match value[4..8].try_into() {
-            Ok(bytes) => TryFrom::<[u8; 4]>::try_from(bytes)?,
-            Err(_) => return Err("Chunk type must be 4 bytes long".into()),
-        };
-        let data = match TryInto::<&[u8]>::try_into(&value[8..(8 + length as usize)]) {
-            Ok(bytes) => bytes.to_vec(),
-            Err(_) => return Err(format!("Chunk data must be {} bytes long", length).into()),
-        };
-        let calculated_crc = calculate_crc(&value[4..(8 + length as usize)]);
-        let crc = match value[(8 + length as usize)..].try_into() {
-            Ok(bytes) => u32::from_be_bytes(bytes),
-            Err(_) => return Err("Chunk CRC must be 4 bytes long".into()),
-        };
-        if crc != calculated_crc {
-            return Err("Chunk CRC does not match calculated CRC".into());
+        if value.len() < 12 {
+            return Err(PngError::Truncated {
+                offset: 0,
+                needed: 12,
+                available: value.len(),
+            });
         }
-        Ok(Chunk {
-            length,
-            chunk_type,
-            data,
-            crc,
-        })
+        let length = u32::from_be_bytes(value[0..4].try_into().unwrap());
+        let type_bytes: [u8; 4] = value[4..8].try_into().unwrap();
+        let chunk_type =
+            ChunkType::try_from(type_bytes).map_err(|_| PngError::InvalidChunkType {
+                offset: Some(4),
+                bytes: type_bytes.to_vec(),
+            })?;
+        let data_end = 8 + length as usize;
+        if value.len() - 12 != length as usize {
+            return Err(PngError::ChunkLengthMismatch {
+                offset: 0,
+                declared: length,
+                available: value.len() - 12,
+            });
+        }
+        let data = value[8..data_end].to_vec();
+        let crc = u32::from_be_bytes(value[data_end..].try_into().unwrap());
+        Chunk::with_crc(chunk_type, data, crc)
     }
 }
 
@@ -61,14 +61,7 @@ impl Display for Chunk {
 impl Chunk {
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
         let length = data.len() as u32;
-        let crc = calculate_crc(
-            &chunk_type
-                .bytes()
-                .iter()
-                .chain(data.iter())
-                .copied()
-                .collect::<Vec<u8>>(),
-        );
+        let crc = calculate_crc(&chunk_type, &data);
         Chunk {
             length,
             chunk_type,
@@ -77,7 +70,41 @@ impl Chunk {
         }
     }
 
-    fn length(&self) -> u32 {
+    pub(crate) fn with_crc(chunk_type: ChunkType, data: Vec<u8>, crc: u32) -> crate::Result<Chunk> {
+        let calculated_crc = calculate_crc(&chunk_type, &data);
+        if crc != calculated_crc {
+            return Err(PngError::CrcMismatch {
+                offset: 0,
+                chunk_type,
+                expected: calculated_crc,
+                actual: crc,
+            });
+        }
+        Ok(Chunk {
+            length: data.len() as u32,
+            chunk_type,
+            data,
+            crc,
+        })
+    }
+
+    // Keeps `crc` even if it is wrong, for reading damaged files.
+    pub(crate) fn with_unchecked_crc(chunk_type: ChunkType, data: Vec<u8>, crc: u32) -> Chunk {
+        Chunk {
+            length: data.len() as u32,
+            chunk_type,
+            data,
+            crc,
+        }
+    }
+
+    /// The CRC the chunk should have, which differs from [`Chunk::crc`]
+    /// only for chunks read from a damaged file.
+    pub fn calculated_crc(&self) -> u32 {
+        calculate_crc(&self.chunk_type, &self.data)
+    }
+
+    pub fn length(&self) -> u32 {
         self.length
     }
 
@@ -85,18 +112,20 @@ impl Chunk {
         &self.chunk_type
     }
 
-    fn data(&self) -> &[u8] {
+    pub fn data(&self) -> &[u8] {
         &self.data
     }
 
-    fn crc(&self) -> u32 {
+    pub fn crc(&self) -> u32 {
         self.crc
     }
 
     pub fn data_as_string(&self) -> crate::Result<String> {
         match String::from_utf8(self.data.clone()) {
             Ok(string) => Ok(string),
-            Err(_) => Err("Chunk data must be valid UTF-8".into()),
+            Err(_) => Err(PngError::InvalidUtf8 {
+                chunk_type: self.chunk_type.clone(),
+            }),
         }
     }
 
@@ -108,6 +137,14 @@ impl Chunk {
         bytes.extend_from_slice(&self.crc.to_be_bytes());
         bytes
     }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> crate::Result<()> {
+        writer.write_all(&self.length.to_be_bytes())?;
+        writer.write_all(&self.chunk_type.bytes())?;
+        writer.write_all(&self.data)?;
+        writer.write_all(&self.crc.to_be_bytes())?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -219,6 +256,35 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_crc_mismatch_error() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let mut chunk_data = Chunk::new(chunk_type, b"Hello".to_vec()).as_bytes();
+        let last = chunk_data.len() - 1;
+        chunk_data[last] ^= 1;
+
+        match Chunk::try_from(chunk_data.as_ref()) {
+            Err(PngError::CrcMismatch {
+                chunk_type,
+                expected,
+                actual,
+                ..
+            }) => {
+                assert_eq!(chunk_type.to_string(), "RuSt");
+                assert_eq!(expected ^ actual, 1);
+            }
+            other => panic!("expected CRC mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_truncated_chunk_from_bytes() {
+        let chunk_data = testing_chunk().as_bytes();
+        for length in 0..chunk_data.len() {
+            assert!(Chunk::try_from(&chunk_data[..length]).is_err());
+        }
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
@@ -0,0 +1,266 @@
+use std::io::{ErrorKind, Read};
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, error::PngError, png::Png, Result};
+
+/// Reads chunks one at a time from a PNG stream, checking the signature up
+/// front and each chunk's CRC as it is read. Reading stops after IEND;
+/// whatever follows it is left for [`ChunkReader::read_trailer`].
+pub struct ChunkReader<R: Read> {
+    reader: R,
+    offset: usize,
+    done: bool,
+}
+
+impl<R: Read> ChunkReader<R> {
+    pub fn new(mut reader: R) -> Result<ChunkReader<R>> {
+        let mut header = [0; 8];
+        let read = read_full(&mut reader, &mut header)?;
+        if read < header.len() {
+            return Err(PngError::Truncated {
+                offset: 0,
+                needed: header.len(),
+                available: read,
+            });
+        }
+        if header != Png::STANDARD_HEADER {
+            return Err(PngError::InvalidSignature {
+                found: header.to_vec(),
+            });
+        }
+        Ok(ChunkReader {
+            reader,
+            offset: header.len(),
+            done: false,
+        })
+    }
+
+    /// Byte offset of the next chunk in the stream.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Reads everything left in the stream. Once the chunks have all been
+    /// read, this is the data appended after IEND.
+    pub fn read_trailer(&mut self) -> Result<Vec<u8>> {
+        let mut trailer = Vec::new();
+        self.reader.read_to_end(&mut trailer)?;
+        self.offset += trailer.len();
+        Ok(trailer)
+    }
+
+    fn read_chunk(&mut self) -> Result<Option<Chunk>> {
+        let mut header = [0; 8];
+        let read = read_full(&mut self.reader, &mut header)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        if read < header.len() {
+            return Err(PngError::Truncated {
+                offset: self.offset,
+                needed: 12,
+                available: read,
+            });
+        }
+        let length = u32::from_be_bytes(header[0..4].try_into().unwrap());
+        let type_bytes: [u8; 4] = header[4..8].try_into().unwrap();
+        let chunk_type =
+            ChunkType::try_from(type_bytes).map_err(|_| PngError::InvalidChunkType {
+                offset: Some(self.offset + 4),
+                bytes: type_bytes.to_vec(),
+            })?;
+
+        // Read through `take` so a bogus length can't make us allocate more
+        // than the stream actually holds.
+        let mut data = Vec::new();
+        (&mut self.reader)
+            .take(length as u64)
+            .read_to_end(&mut data)?;
+        if data.len() < length as usize {
+            return Err(PngError::ChunkLengthMismatch {
+                offset: self.offset,
+                declared: length,
+                available: data.len(),
+            });
+        }
+
+        let mut crc = [0; 4];
+        let read = read_full(&mut self.reader, &mut crc)?;
+        if read < crc.len() {
+            return Err(PngError::Truncated {
+                offset: self.offset + 8 + data.len(),
+                needed: crc.len(),
+                available: read,
+            });
+        }
+        let chunk = Chunk::with_crc(chunk_type, data, u32::from_be_bytes(crc))
+            .map_err(|error| error.shift_offset(self.offset))?;
+        self.offset += 12 + chunk.length() as usize;
+        Ok(Some(chunk))
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.read_chunk() {
+            Ok(Some(chunk)) => {
+                self.done = chunk.chunk_type() == &ChunkType::IEND;
+                Some(Ok(chunk))
+            }
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(error) => {
+                self.done = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+// Like `read_exact`, but reports how many bytes were read before the end of
+// the stream instead of failing.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error.into()),
+        }
+    }
+    Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::str::FromStr;
+
+    fn testing_chunks() -> Vec<Chunk> {
+        vec![
+            Chunk::new(
+                ChunkType::from_str("FrSt").unwrap(),
+                b"I am the first chunk".to_vec(),
+            ),
+            Chunk::new(
+                ChunkType::from_str("miDl").unwrap(),
+                b"I am another chunk".to_vec(),
+            ),
+            Chunk::new(
+                ChunkType::from_str("LASt").unwrap(),
+                b"I am the last chunk".to_vec(),
+            ),
+        ]
+    }
+
+    fn testing_bytes() -> Vec<u8> {
+        Png::from_chunks(testing_chunks()).as_bytes()
+    }
+
+    #[test]
+    fn test_reads_all_chunks() {
+        let reader = ChunkReader::new(Cursor::new(testing_bytes())).unwrap();
+        let chunks: Vec<Chunk> = reader.collect::<Result<_>>().unwrap();
+        let types: Vec<String> = chunks.iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["FrSt", "miDl", "LASt"]);
+        assert_eq!(chunks[1].data_as_string().unwrap(), "I am another chunk");
+    }
+
+    #[test]
+    fn test_offset() {
+        let mut reader = ChunkReader::new(Cursor::new(testing_bytes())).unwrap();
+        assert_eq!(reader.offset(), 8);
+        reader.next().unwrap().unwrap();
+        assert_eq!(reader.offset(), 8 + 12 + "I am the first chunk".len());
+    }
+
+    #[test]
+    fn test_invalid_signature() {
+        let mut bytes = testing_bytes();
+        bytes[0] = 13;
+        assert!(matches!(
+            ChunkReader::new(Cursor::new(bytes)),
+            Err(PngError::InvalidSignature { .. })
+        ));
+    }
+
+    #[test]
+    fn test_crc_mismatch_stops_iteration() {
+        let mut bytes = testing_bytes();
+        let second_chunk_offset = 8 + 12 + "I am the first chunk".len();
+        bytes[second_chunk_offset + 8] ^= 0xff;
+
+        let mut reader = ChunkReader::new(Cursor::new(bytes)).unwrap();
+        assert!(reader.next().unwrap().is_ok());
+        match reader.next() {
+            Some(Err(PngError::CrcMismatch { offset, .. })) => {
+                assert_eq!(offset, second_chunk_offset)
+            }
+            other => panic!("expected CRC mismatch, got {:?}", other),
+        }
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_truncated_stream() {
+        let bytes = testing_bytes();
+        for length in 0..bytes.len() {
+            let reader = match ChunkReader::new(Cursor::new(&bytes[..length])) {
+                Ok(reader) => reader,
+                Err(_) => continue,
+            };
+            let read: Result<Vec<Chunk>> = reader.collect();
+            if let Ok(chunks) = read {
+                let png = Png::from_chunks(chunks);
+                assert_eq!(png.as_bytes(), &bytes[..length]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_stops_at_iend() {
+        let mut bytes = Png::from_chunks(vec![
+            Chunk::new(ChunkType::IEND, vec![]),
+            testing_chunks().remove(0),
+        ])
+        .as_bytes();
+        bytes.extend_from_slice(b"PK\x03\x04");
+
+        let mut reader = ChunkReader::new(Cursor::new(bytes)).unwrap();
+        let chunks: Vec<Chunk> = reader.by_ref().collect::<Result<_>>().unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(reader.offset(), 20);
+        let trailer = reader.read_trailer().unwrap();
+        assert_eq!(trailer.len(), 12 + "I am the first chunk".len() + 4);
+        assert!(trailer.ends_with(b"PK\x03\x04"));
+    }
+
+    #[test]
+    fn test_huge_declared_length() {
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        bytes.extend_from_slice(&u32::MAX.to_be_bytes());
+        bytes.extend_from_slice(b"RuSt");
+        bytes.extend_from_slice(&[0; 8]);
+
+        let mut reader = ChunkReader::new(Cursor::new(bytes)).unwrap();
+        match reader.next() {
+            Some(Err(PngError::ChunkLengthMismatch {
+                declared,
+                available,
+                ..
+            })) => {
+                assert_eq!(declared, u32::MAX);
+                assert_eq!(available, 8);
+            }
+            other => panic!("expected length mismatch, got {:?}", other),
+        }
+    }
+}
@@ -3,6 +3,10 @@ use std::{
     str::{from_utf8, FromStr},
 };
 
+use serde::{Serialize, Serializer};
+
+use crate::error::PngError;
+
 fn is_valid_ascii_string(value: [u8; 4]) -> bool {
     for byte in value {
         if !byte.is_ascii_alphabetic() {
@@ -23,7 +27,10 @@ impl TryFrom<[u8; 4]> for ChunkType {
         if is_valid_ascii_string(value) {
             Ok(ChunkType { bytes: value })
         } else {
-            Err("Chunk type must contain only ASCII letters".into())
+            Err(PngError::InvalidChunkType {
+                offset: None,
+                bytes: value.to_vec(),
+            })
         }
     }
 }
@@ -31,15 +38,12 @@ impl TryFrom<[u8; 4]> for ChunkType {
 impl FromStr for ChunkType {
     type Err = crate::Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.as_bytes().try_into() {
-            Ok(bytes) => {
-                if is_valid_ascii_string(bytes) {
-                    Ok(ChunkType { bytes })
-                } else {
-                    Err("Chunk type must contain only ASCII letters".into())
-                }
-            }
-            Err(_) => Err("Chunk type must be 4 characters long".into()),
+        match TryInto::<[u8; 4]>::try_into(s.as_bytes()) {
+            Ok(bytes) => ChunkType::try_from(bytes),
+            Err(_) => Err(PngError::InvalidChunkType {
+                offset: None,
+                bytes: s.as_bytes().to_vec(),
+            }),
         }
     }
 }
@@ -50,33 +54,50 @@ impl Display for ChunkType {
     }
 }
 
+impl Serialize for ChunkType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
 impl ChunkType {
+    pub const IHDR: ChunkType = ChunkType { bytes: *b"IHDR" };
+    pub const PLTE: ChunkType = ChunkType { bytes: *b"PLTE" };
+    pub const IDAT: ChunkType = ChunkType { bytes: *b"IDAT" };
+    pub const IEND: ChunkType = ChunkType { bytes: *b"IEND" };
+    pub const TEXT: ChunkType = ChunkType { bytes: *b"tEXt" };
+    pub const ZTXT: ChunkType = ChunkType { bytes: *b"zTXt" };
+    pub const ITXT: ChunkType = ChunkType { bytes: *b"iTXt" };
+    pub const ACTL: ChunkType = ChunkType { bytes: *b"acTL" };
+    pub const FCTL: ChunkType = ChunkType { bytes: *b"fcTL" };
+    pub const FDAT: ChunkType = ChunkType { bytes: *b"fdAT" };
+
     pub fn bytes(&self) -> [u8; 4] {
-        return self.bytes;
+        self.bytes
     }
 
-    fn is_valid(&self) -> bool {
-        return is_valid_ascii_string(self.bytes) && self.is_reserved_bit_valid();
+    pub fn is_valid(&self) -> bool {
+        is_valid_ascii_string(self.bytes) && self.is_reserved_bit_valid()
     }
 
-    fn is_critical(&self) -> bool {
+    pub fn is_critical(&self) -> bool {
         let first_byte = self.bytes[0];
-        return (first_byte >> 5 & 1) == 0;
+        (first_byte >> 5 & 1) == 0
     }
 
-    fn is_public(&self) -> bool {
+    pub fn is_public(&self) -> bool {
         let second_byte = self.bytes[1];
-        return (second_byte >> 5 & 1) == 0;
+        (second_byte >> 5 & 1) == 0
     }
 
-    fn is_reserved_bit_valid(&self) -> bool {
+    pub fn is_reserved_bit_valid(&self) -> bool {
         let third_byte = self.bytes[2];
-        return (third_byte >> 5 & 1) == 0;
+        (third_byte >> 5 & 1) == 0
     }
 
-    fn is_safe_to_copy(&self) -> bool {
+    pub fn is_safe_to_copy(&self) -> bool {
         let fourth_byte = self.bytes[3];
-        return (fourth_byte >> 5 & 1) == 1;
+        (fourth_byte >> 5 & 1) == 1
     }
 }
 
@@ -1,52 +1,1046 @@
-use std::{fs, path::PathBuf, str::FromStr};
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use crate::{chunk::Chunk, chunk_type::ChunkType, png::Png, Result};
+use serde::Serialize;
+
+use crate::{
+    analyze,
+    apng::{self, Animation, AnimationControl},
+    chunk::Chunk,
+    chunk_reader::ChunkReader,
+    chunk_type::ChunkType,
+    compress::{self, Compression},
+    crypto, diff,
+    error::PngError,
+    ihdr::Ihdr,
+    image::{EncodingOptions, ImageData},
+    output::{self, ChunkRecord, Data, OutputFormat},
+    payload::{self, FilePayload},
+    png::{ChunkPosition, Png},
+    png_writer::PngWriter,
+    repair,
+    signature::{self, SignatureHasher, SignatureStatus},
+    stego::{self, StegoOptions},
+    text::{self, TextChunk},
+    trailer, Result,
+};
+
+type FileWriter = PngWriter<BufWriter<File>>;
+
+#[derive(Debug, Clone)]
+pub struct EncodeOptions {
+    pub position: ChunkPosition,
+    pub passphrase: Option<String>,
+    pub recipient: Option<PathBuf>,
+    pub sign: Option<PathBuf>,
+    pub sign_critical: bool,
+    /// Split the payload across chunks holding at most this many bytes.
+    pub max_chunk_size: Option<usize>,
+    pub compression: Option<Compression>,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        EncodeOptions {
+            position: ChunkPosition::BeforeIend,
+            passphrase: None,
+            recipient: None,
+            sign: None,
+            sign_critical: false,
+            max_chunk_size: None,
+            compression: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyKind {
+    Recipient,
+    Signing,
+}
+
+#[derive(Debug, Clone)]
+pub struct DecodeOptions {
+    pub passphrase: Option<String>,
+    pub identity: Option<PathBuf>,
+    /// Write the decoded bytes to this file instead of returning them.
+    pub output: Option<PathBuf>,
+    /// Write the decoded bytes to standard output untouched.
+    pub raw: bool,
+    /// Refuse compressed payloads that inflate to more than this many bytes.
+    pub max_output_size: usize,
+    /// Without `output` or `raw`, return the bytes in this format.
+    pub format: OutputFormat,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        DecodeOptions {
+            passphrase: None,
+            identity: None,
+            output: None,
+            raw: false,
+            max_output_size: compress::DEFAULT_MAX_OUTPUT_SIZE,
+            format: OutputFormat::Text,
+        }
+    }
+}
+
+// A decoded payload for structured output.
+#[derive(Serialize)]
+struct PayloadRecord {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_name: Option<String>,
+    #[serde(flatten)]
+    data: Data,
+}
+
+/// Where the bytes hidden by [`encode`] come from.
+#[derive(Debug, Clone)]
+pub enum EncodeInput {
+    Message(String),
+    File(PathBuf),
+    Stdin,
+}
+
+impl EncodeInput {
+    fn into_bytes(self) -> Result<Vec<u8>> {
+        match self {
+            EncodeInput::Message(message) => Ok(payload::escape_message(message.into_bytes())),
+            EncodeInput::File(path) => {
+                let name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                Ok(FilePayload::new(name, fs::read(&path)?).to_bytes())
+            }
+            EncodeInput::Stdin => {
+                let mut contents = Vec::new();
+                io::stdin().lock().read_to_end(&mut contents)?;
+                Ok(FilePayload::new(String::new(), contents).to_bytes())
+            }
+        }
+    }
+}
+
+fn read_key_file(key_path: &PathBuf) -> Result<[u8; crypto::KEY_LEN]> {
+    crypto::decode_key(&fs::read_to_string(key_path)?)
+}
+
+fn seal(data: Vec<u8>, options: &EncodeOptions) -> Result<Vec<u8>> {
+    if let Some(passphrase) = &options.passphrase {
+        return crypto::encrypt_with_passphrase(&data, passphrase);
+    }
+    if let Some(recipient) = &options.recipient {
+        return crypto::seal_for_recipient(&data, &read_key_file(recipient)?);
+    }
+    Ok(data)
+}
+
+fn unseal(data: Vec<u8>, options: &DecodeOptions) -> Result<Vec<u8>> {
+    if let Some(passphrase) = &options.passphrase {
+        return crypto::decrypt_with_passphrase(&data, passphrase);
+    }
+    if let Some(identity) = &options.identity {
+        return crypto::open_with_identity(&data, &read_key_file(identity)?);
+    }
+    if crypto::is_sealed(&data) {
+        return Err(PngError::InvalidPayload {
+            reason: "message is encrypted and no passphrase or identity was given".into(),
+        });
+    }
+    Ok(data)
+}
 
 fn open_png_file(file_path: &PathBuf) -> Result<Png> {
     let file = fs::read(file_path)?;
-    TryFrom::try_from(file.as_ref())
+    Png::try_from(file.as_slice())
+}
+
+fn open_chunk_reader(file_path: &PathBuf) -> Result<ChunkReader<BufReader<File>>> {
+    let file = File::open(file_path)?;
+    ChunkReader::new(BufReader::new(file))
+}
+
+// Writes `png` to a temporary file first so a failure never leaves
+// `output_file` half written.
+fn write_png_file(png: &Png, output_file: &Path) -> Result<()> {
+    let temp_path = temp_path_for(output_file);
+    match fs::write(&temp_path, png.as_bytes()) {
+        Ok(()) => Ok(fs::rename(&temp_path, output_file)?),
+        Err(error) => {
+            let _ = fs::remove_file(&temp_path);
+            Err(error.into())
+        }
+    }
+}
+
+fn temp_path_for(output_file: &Path) -> PathBuf {
+    let mut file_name = OsString::from(".");
+    file_name.push(output_file.file_name().unwrap_or_default());
+    file_name.push(".pngme-tmp");
+    output_file.with_file_name(file_name)
+}
+
+// Copies the chunks of `file_path` into `output_file` one at a time, letting
+// `edit` decide what gets written for each chunk. `edit` is called one last
+// time with `None` once the input is exhausted. The output is written to a
+// temporary file first so `output_file` may be the input itself. Any data
+// after IEND is copied over untouched.
+fn rewrite_png_file<F>(file_path: &PathBuf, output_file: &Path, mut edit: F) -> Result<()>
+where
+    F: FnMut(&mut FileWriter, Option<Chunk>) -> Result<()>,
+{
+    let mut reader = open_chunk_reader(file_path)?;
+    let temp_path = temp_path_for(output_file);
+    let mut copy = || -> Result<()> {
+        let mut writer = PngWriter::new(BufWriter::new(File::create(&temp_path)?))?;
+        for chunk in reader.by_ref() {
+            edit(&mut writer, Some(chunk?))?;
+        }
+        edit(&mut writer, None)?;
+        writer.write_trailer(&reader.read_trailer()?)?;
+        writer.finish()?;
+        Ok(())
+    };
+    match copy() {
+        Ok(()) => Ok(fs::rename(&temp_path, output_file)?),
+        Err(error) => {
+            let _ = fs::remove_file(&temp_path);
+            Err(error)
+        }
+    }
+}
+
+fn write_hashed(
+    writer: &mut FileWriter,
+    chunk: &Chunk,
+    hasher: Option<&mut SignatureHasher>,
+) -> Result<()> {
+    if let Some(hasher) = hasher {
+        hasher.update(chunk);
+    }
+    writer.write_chunk(chunk)
+}
+
+fn write_signature(
+    writer: &mut FileWriter,
+    hasher: Option<SignatureHasher>,
+    signing_key: Option<&[u8; crypto::KEY_LEN]>,
+) -> Result<()> {
+    match (hasher, signing_key) {
+        (Some(hasher), Some(key)) => writer.write_chunk(&hasher.sign(key)),
+        _ => Ok(()),
+    }
 }
 
 pub fn encode(
     file_path: PathBuf,
     chunk_type: String,
-    message: String,
+    input: EncodeInput,
     output_file: Option<PathBuf>,
+    options: EncodeOptions,
 ) -> Result<String> {
-    let mut png = open_png_file(&file_path)?;
     let chunk_type: ChunkType = ChunkType::from_str(chunk_type.as_str())?;
-    let chunk = Chunk::new(chunk_type, message.as_bytes().to_vec());
-    png.append_chunk(chunk);
-    if let Some(output_file) = output_file {
-        fs::write(output_file, png.as_bytes())?;
+    check_animation_chunk(&chunk_type, "encoding into")?;
+    let data = pack_payload(input, &options)?;
+    let signing_key = match &options.sign {
+        Some(key_path) => Some(read_key_file(key_path)?),
+        None => None,
+    };
+    let mut hasher =
+        signing_key.map(|_| SignatureHasher::new(chunk_type.clone(), options.sign_critical));
+    let parts = match options.max_chunk_size {
+        Some(max_chunk_size) if data.len() > max_chunk_size => {
+            payload::split(&data, max_chunk_size)?
+        }
+        _ => vec![data],
+    };
+    let new_chunks: Vec<Chunk> = parts
+        .into_iter()
+        .map(|part| Chunk::new(chunk_type.clone(), part))
+        .collect();
+    let position = options.position;
+    let output_file = output_file.unwrap_or_else(|| file_path.clone());
+    let mut previous: Option<ChunkType> = None;
+    let mut inserted = false;
+    rewrite_png_file(&file_path, &output_file, |writer, chunk| {
+        let chunk = match chunk {
+            Some(chunk) => chunk,
+            None => {
+                if !inserted {
+                    for new_chunk in &new_chunks {
+                        write_hashed(writer, new_chunk, hasher.as_mut())?;
+                    }
+                }
+                return write_signature(writer, hasher.take(), signing_key.as_ref());
+            }
+        };
+        // A file without the place asked for gets the chunks right before
+        // IEND, never after it.
+        let is_iend = chunk.chunk_type() == &ChunkType::IEND;
+        if !inserted
+            && (is_iend || position.is_insertion_point(previous.as_ref(), chunk.chunk_type()))
+        {
+            for new_chunk in &new_chunks {
+                write_hashed(writer, new_chunk, hasher.as_mut())?;
+            }
+            inserted = true;
+        }
+        // The signature goes right before IEND, once everything it covers
+        // has been written.
+        if is_iend {
+            write_signature(writer, hasher.take(), signing_key.as_ref())?;
+        }
+        previous = Some(chunk.chunk_type().clone());
+        write_hashed(writer, &chunk, hasher.as_mut())
+    })?;
+    Ok("Message succesfully encoded".into())
+}
+
+pub fn decode(file_path: PathBuf, chunk_type: String, options: DecodeOptions) -> Result<String> {
+    let chunk_type = ChunkType::from_str(chunk_type.as_str())?;
+    let data = read_payload(&file_path, &chunk_type)?;
+    unpack_payload(data, &options, PngError::InvalidUtf8 { chunk_type })
+}
+
+// Turns the input into the bytes to hide: compressed, then encrypted.
+fn pack_payload(input: EncodeInput, options: &EncodeOptions) -> Result<Vec<u8>> {
+    let mut data = input.into_bytes()?;
+    if let Some(compression) = options.compression {
+        data = compress::compress(&data, compression)?;
+    }
+    seal(data, options)
+}
+
+// Reverses pack_payload and delivers the result as the options ask. Text is
+// returned directly, failing with `not_utf8` if it is not valid UTF-8, unless
+// a structured format was asked for.
+fn unpack_payload(data: Vec<u8>, options: &DecodeOptions, not_utf8: PngError) -> Result<String> {
+    let mut data = unseal(data, options)?;
+    if compress::is_compressed(&data) {
+        data = compress::decompress(&data, options.max_output_size)?;
+    }
+    let mut name = String::new();
+    if payload::is_file_payload(&data) {
+        let file = FilePayload::try_from(data.as_slice())?;
+        (name, data) = (file.name, file.contents);
     } else {
-        fs::write(file_path, png.as_bytes())?;
+        data = payload::unescape_message(data);
     }
-    Ok("Message succesfully encoded".into())
+    if let Some(output) = &options.output {
+        fs::write(output, &data)?;
+        let original = match name.is_empty() {
+            true => String::new(),
+            false => format!(" (originally {})", name),
+        };
+        return Ok(format!(
+            "Wrote {} bytes to {}{}",
+            data.len(),
+            output.display(),
+            original
+        ));
+    }
+    if options.raw {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&data)?;
+        stdout.flush()?;
+        return Ok(String::new());
+    }
+    if let Some(format) = options.format.structured() {
+        let record = PayloadRecord {
+            file_name: Some(name).filter(|name| !name.is_empty()),
+            data: Data::new(data),
+        };
+        return Ok(output::render(&record, format));
+    }
+    String::from_utf8(data).map_err(|_| not_utf8)
 }
 
-pub fn decode(file_path: PathBuf, chunk_type: String) -> Result<String> {
-    let png = open_png_file(&file_path)?;
-    match png.chunk_by_type(&chunk_type) {
-        Some(chunk) => Ok(format!("{}", chunk.data_as_string()?)),
-        None => Err("Chunk not found".into()),
+// Reads the payload hidden in `chunk_type` chunks, stopping at the first
+// one unless it holds one part of a split payload, in which case reading
+// stops once every part of that payload has been found.
+fn read_payload(file_path: &PathBuf, chunk_type: &ChunkType) -> Result<Vec<u8>> {
+    let mut chunks = open_chunk_reader(file_path)?
+        .filter(|chunk| !matches!(chunk, Ok(chunk) if chunk.chunk_type() != chunk_type));
+    let first = match chunks.next() {
+        Some(chunk) => chunk?,
+        None => {
+            return Err(PngError::ChunkNotFound {
+                chunk_type: chunk_type.clone(),
+            })
+        }
+    };
+    if !payload::is_part(first.data()) {
+        return Ok(first.data().to_vec());
+    }
+    let (id, total) = payload::part_header(first.data())?;
+    let mut parts = vec![first];
+    while parts.len() < total as usize {
+        let Some(chunk) = chunks.next() else { break };
+        let chunk = chunk?;
+        if payload::is_part(chunk.data()) && payload::part_header(chunk.data())?.0 == id {
+            parts.push(chunk);
+        }
     }
+    payload::join(parts.iter().map(|chunk| chunk.data()))
+}
+
+// Adding or dropping animation chunks by hand would break the frame count
+// and sequence numbers the animation depends on.
+fn check_animation_chunk(chunk_type: &ChunkType, action: &str) -> Result<()> {
+    if apng::is_animation_chunk(chunk_type) {
+        return Err(PngError::InvalidAnimation {
+            reason: format!(
+                "{} a {} chunk would break the frame sequence",
+                action, chunk_type
+            ),
+        });
+    }
+    Ok(())
 }
 
 pub fn remove(file_path: PathBuf, chunk_type: String) -> Result<String> {
-    let mut png = open_png_file(&file_path)?;
-    png.remove_chunk(&chunk_type)?;
-    fs::write(file_path, png.as_bytes())?;
+    let chunk_type = ChunkType::from_str(chunk_type.as_str())?;
+    check_animation_chunk(&chunk_type, "removing")?;
+    let mut removed = false;
+    rewrite_png_file(&file_path, &file_path, |writer, chunk| match chunk {
+        Some(chunk) if !removed && chunk.chunk_type() == &chunk_type => {
+            removed = true;
+            Ok(())
+        }
+        Some(chunk) => writer.write_chunk(&chunk),
+        None if removed => Ok(()),
+        None => Err(PngError::ChunkNotFound {
+            chunk_type: chunk_type.clone(),
+        }),
+    })?;
     Ok("Chunk successfully removed".into())
 }
 
-pub fn print(file_path: PathBuf) -> Result<String> {
-    let png = open_png_file(&file_path)?;
+fn read_chunk_records(file_path: &PathBuf) -> Result<Vec<ChunkRecord>> {
+    let mut reader = open_chunk_reader(file_path)?;
+    let mut records = Vec::new();
+    loop {
+        let offset = reader.offset();
+        match reader.next() {
+            Some(chunk) => records.push(ChunkRecord::new(records.len(), offset, &chunk?)),
+            None => return Ok(records),
+        }
+    }
+}
+
+fn describe_properties(chunk_type: &ChunkType) -> String {
+    let mut properties = vec![
+        match chunk_type.is_critical() {
+            true => "critical",
+            false => "ancillary",
+        },
+        match chunk_type.is_public() {
+            true => "public",
+            false => "private",
+        },
+        match chunk_type.is_safe_to_copy() {
+            true => "safe to copy",
+            false => "unsafe to copy",
+        },
+    ];
+    if !chunk_type.is_reserved_bit_valid() {
+        properties.push("reserved bit set");
+    }
+    properties.join(", ")
+}
+
+/// Lists every chunk, with a hex dump of up to `max_bytes` of each
+/// payload when `hexdump` is set.
+pub fn list(
+    file_path: PathBuf,
+    hexdump: bool,
+    max_bytes: usize,
+    format: OutputFormat,
+) -> Result<String> {
+    if let Some(format) = format.structured() {
+        return Ok(output::render(&read_chunk_records(&file_path)?, format));
+    }
+    let mut reader = open_chunk_reader(&file_path)?;
+    let mut lines = vec![format!(
+        "{:>5}  {:>10}  {:>10}  {:<10}  {:<4}  Properties",
+        "Index", "Offset", "Length", "CRC", "Type"
+    )];
+    let mut index = 0;
+    loop {
+        let offset = reader.offset();
+        let chunk = match reader.next() {
+            Some(chunk) => chunk?,
+            None => break,
+        };
+        lines.push(format!(
+            "{:>5}  {:>10}  {:>10}  {:#010x}  {}  {}",
+            index,
+            offset,
+            chunk.length(),
+            chunk.crc(),
+            chunk.chunk_type(),
+            describe_properties(chunk.chunk_type())
+        ));
+        if hexdump && !chunk.data().is_empty() {
+            lines.push(output::hexdump(chunk.data(), max_bytes));
+        }
+        index += 1;
+    }
+    let trailer = reader.read_trailer()?;
+    if !trailer.is_empty() {
+        lines.push(format!(
+            "{} bytes follow IEND at offset {}",
+            trailer.len(),
+            reader.offset() - trailer.len()
+        ));
+    }
+    Ok(lines.join("\n"))
+}
+
+pub fn print(file_path: PathBuf, format: OutputFormat) -> Result<String> {
+    if let Some(format) = format.structured() {
+        return Ok(output::render(&read_chunk_records(&file_path)?, format));
+    }
     let mut messages = String::new();
-    for chunk in png.chunks() {
-        if let Ok(message) = chunk.data_as_string() {
+    for chunk in open_chunk_reader(&file_path)? {
+        let chunk = chunk?;
+        if text::is_text_chunk(chunk.chunk_type()) {
+            match TextChunk::try_from(&chunk) {
+                Ok(text_chunk) => {
+                    messages.push_str(format!("{}: {}\n", chunk.chunk_type(), text_chunk).as_str())
+                }
+                Err(error) => {
+                    messages.push_str(format!("{}: <{}>\n", chunk.chunk_type(), error).as_str())
+                }
+            }
+        } else if let Ok(message) = chunk.data_as_string() {
             messages.push_str(format!("{}: {}\n", chunk.chunk_type(), message).as_str());
         }
     }
     Ok(messages)
 }
+
+pub fn check(file_path: PathBuf) -> Result<String> {
+    let png = open_png_file(&file_path)?;
+    let violations = png.validate();
+    if violations.is_empty() {
+        Ok("No problems found".into())
+    } else {
+        Err(PngError::Malformed { violations })
+    }
+}
+
+pub fn info(file_path: PathBuf, format: OutputFormat) -> Result<String> {
+    // IHDR must be the first chunk, so there is no need to read further.
+    let chunk = match open_chunk_reader(&file_path)?.next() {
+        Some(chunk) => chunk?,
+        None => {
+            return Err(PngError::ChunkNotFound {
+                chunk_type: ChunkType::IHDR,
+            })
+        }
+    };
+    let ihdr = Ihdr::try_from(&chunk)?;
+    if let Some(format) = format.structured() {
+        return Ok(output::render(&ihdr, format));
+    }
+    Ok(format!(
+        "Width: {}\nHeight: {}\nBit depth: {}\nColor type: {}\nCompression method: {}\nFilter method: {}\nInterlace method: {}",
+        ihdr.width,
+        ihdr.height,
+        ihdr.bit_depth,
+        ihdr.color_type,
+        ihdr.compression_method,
+        ihdr.filter_method,
+        ihdr.interlace_method
+    ))
+}
+
+pub fn keygen(output_prefix: PathBuf, kind: KeyKind) -> Result<String> {
+    let (secret, public) = match kind {
+        KeyKind::Recipient => crypto::generate_recipient_keys(),
+        KeyKind::Signing => signature::generate_signing_keys(),
+    };
+    let secret_path = output_prefix.with_extension("key");
+    let public_path = output_prefix.with_extension("pub");
+    write_secret_file(&secret_path, &crypto::encode_key(&secret))?;
+    fs::write(&public_path, crypto::encode_key(&public) + "\n")?;
+    Ok(format!(
+        "Secret key written to {}\nPublic key written to {}",
+        secret_path.display(),
+        public_path.display()
+    ))
+}
+
+pub fn verify(file_path: PathBuf, key: PathBuf) -> Result<String> {
+    let png = open_png_file(&file_path)?;
+    let results = signature::verify_chunks(png.chunks(), &read_key_file(&key)?)?;
+    if results.is_empty() {
+        return Err(PngError::SignatureMissing);
+    }
+    if results
+        .iter()
+        .any(|(_, status)| *status != SignatureStatus::Valid)
+    {
+        return Err(PngError::SignatureInvalid { results });
+    }
+    Ok(results
+        .iter()
+        .map(|(chunk_type, status)| format!("{}: {}", chunk_type, status))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+fn write_secret_file(path: &Path, key: &str) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    writeln!(file, "{}", key)?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TextOptions {
+    pub compress: bool,
+    pub language_tag: Option<String>,
+    pub translated_keyword: Option<String>,
+}
+
+// Reads every text chunk in the file, skipping ones that fail to parse.
+fn read_text_chunks(file_path: &PathBuf) -> Result<Vec<TextChunk>> {
+    let mut text_chunks = Vec::new();
+    for chunk in open_chunk_reader(file_path)? {
+        let chunk = chunk?;
+        if text::is_text_chunk(chunk.chunk_type()) {
+            if let Ok(text_chunk) = TextChunk::try_from(&chunk) {
+                text_chunks.push(text_chunk);
+            }
+        }
+    }
+    Ok(text_chunks)
+}
+
+fn has_keyword(chunk: &Chunk, keyword: &str) -> bool {
+    text::is_text_chunk(chunk.chunk_type())
+        && matches!(TextChunk::try_from(chunk), Ok(text_chunk) if text_chunk.keyword() == keyword)
+}
+
+pub fn text_list(file_path: PathBuf) -> Result<String> {
+    Ok(read_text_chunks(&file_path)?
+        .iter()
+        .map(|text_chunk| format!("{} ({})", text_chunk, text_chunk.chunk_type()))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+pub fn text_get(file_path: PathBuf, keyword: String) -> Result<String> {
+    let texts: Vec<String> = read_text_chunks(&file_path)?
+        .into_iter()
+        .filter(|text_chunk| text_chunk.keyword() == keyword)
+        .map(|text_chunk| text_chunk.text().to_string())
+        .collect();
+    if texts.is_empty() {
+        return Err(PngError::KeywordNotFound { keyword });
+    }
+    Ok(texts.join("\n"))
+}
+
+/// Stores `text` under `keyword`, replacing any text chunks that already use
+/// the keyword. Latin-1 text goes in tEXt (or zTXt when compressed) and
+/// anything else in iTXt.
+pub fn text_set(
+    file_path: PathBuf,
+    keyword: String,
+    text: String,
+    options: TextOptions,
+) -> Result<String> {
+    let international = options.language_tag.is_some()
+        || options.translated_keyword.is_some()
+        || !text::is_latin1(&text);
+    let text_chunk = match (international, options.compress) {
+        (true, compressed) => TextChunk::InternationalText {
+            keyword: keyword.clone(),
+            compressed,
+            language_tag: options.language_tag.unwrap_or_default(),
+            translated_keyword: options.translated_keyword.unwrap_or_default(),
+            text,
+        },
+        (false, true) => TextChunk::CompressedText {
+            keyword: keyword.clone(),
+            text,
+        },
+        (false, false) => TextChunk::new(&keyword, &text)?,
+    };
+    let new_chunk = text_chunk.to_chunk()?;
+
+    let mut written = false;
+    rewrite_png_file(&file_path, &file_path, |writer, chunk| {
+        let chunk = match chunk {
+            Some(chunk) => chunk,
+            None if written => return Ok(()),
+            None => return writer.write_chunk(&new_chunk),
+        };
+        if has_keyword(&chunk, &keyword) {
+            if !written {
+                written = true;
+                writer.write_chunk(&new_chunk)?;
+            }
+            return Ok(());
+        }
+        if !written && chunk.chunk_type() == &ChunkType::IEND {
+            written = true;
+            writer.write_chunk(&new_chunk)?;
+        }
+        writer.write_chunk(&chunk)
+    })?;
+    Ok(format!("Set {} in {}", keyword, new_chunk.chunk_type()))
+}
+
+pub fn text_remove(file_path: PathBuf, keyword: String) -> Result<String> {
+    let mut removed = 0;
+    rewrite_png_file(&file_path, &file_path, |writer, chunk| match chunk {
+        Some(chunk) if has_keyword(&chunk, &keyword) => {
+            removed += 1;
+            Ok(())
+        }
+        Some(chunk) => writer.write_chunk(&chunk),
+        None if removed > 0 => Ok(()),
+        None => Err(PngError::KeywordNotFound {
+            keyword: keyword.clone(),
+        }),
+    })?;
+    Ok(format!(
+        "Removed {} text chunk(s) with keyword {}",
+        removed, keyword
+    ))
+}
+
+/// Hides a payload in the pixels of `file_path`. Only the encryption and
+/// compression settings of `options` apply.
+pub fn stego_embed(
+    file_path: PathBuf,
+    input: EncodeInput,
+    output_file: Option<PathBuf>,
+    stego_options: StegoOptions,
+    options: EncodeOptions,
+) -> Result<String> {
+    let mut png = open_png_file(&file_path)?;
+    let mut image = ImageData::decode(&png)?;
+    let data = pack_payload(input, &options)?;
+    stego::embed(&mut image, &data, &stego_options)?;
+    png.replace_image_data(image.encode(&EncodingOptions::default())?);
+    write_png_file(&png, &output_file.unwrap_or(file_path))?;
+    Ok(format!("Hid {} bytes in the image", data.len()))
+}
+
+pub fn stego_extract(
+    file_path: PathBuf,
+    stego_options: StegoOptions,
+    options: DecodeOptions,
+) -> Result<String> {
+    let image = ImageData::decode(&open_png_file(&file_path)?)?;
+    let data = stego::extract(&image, &stego_options)?;
+    let not_utf8 = PngError::InvalidPayload {
+        reason: "hidden message is not valid UTF-8; use --output or --raw".into(),
+    };
+    unpack_payload(data, &options, not_utf8)
+}
+
+pub fn stego_capacity(file_path: PathBuf, stego_options: StegoOptions) -> Result<String> {
+    let png = open_png_file(&file_path)?;
+    let capacity = stego::capacity(&png.header_info()?, &stego_options)?;
+    Ok(format!("Capacity: {} bytes", capacity))
+}
+
+pub fn analyze(file_path: PathBuf) -> Result<String> {
+    let report = analyze::analyze(&fs::read(file_path)?)?;
+    Ok(report.to_string())
+}
+
+pub fn trailer_show(file_path: PathBuf) -> Result<String> {
+    let file = fs::read(&file_path)?;
+    let png = Png::try_from(file.as_slice())?;
+    let trailer = png.trailer();
+    if trailer.is_empty() {
+        return Ok("No data follows IEND".into());
+    }
+    Ok(format!(
+        "{} bytes follow IEND at offset {}\nLooks like: {}",
+        trailer.len(),
+        file.len() - trailer.len(),
+        trailer::identify(trailer).unwrap_or("unknown data")
+    ))
+}
+
+pub fn trailer_extract(file_path: PathBuf, output: PathBuf) -> Result<String> {
+    let png = open_png_file(&file_path)?;
+    if png.trailer().is_empty() {
+        return Ok("No data follows IEND".into());
+    }
+    fs::write(&output, png.trailer())?;
+    Ok(format!(
+        "Wrote {} bytes to {}",
+        png.trailer().len(),
+        output.display()
+    ))
+}
+
+pub fn trailer_strip(file_path: PathBuf, output_file: Option<PathBuf>) -> Result<String> {
+    let mut png = open_png_file(&file_path)?;
+    let removed = png.trailer().len();
+    png.set_trailer(Vec::new());
+    write_png_file(&png, &output_file.unwrap_or(file_path))?;
+    Ok(format!("Removed {} bytes after IEND", removed))
+}
+
+pub fn apng_frames(file_path: PathBuf) -> Result<String> {
+    let animation = Animation::parse(&open_png_file(&file_path)?)?;
+    let plays = match animation.control.num_plays {
+        0 => "forever".to_string(),
+        plays => format!("{} time(s)", plays),
+    };
+    let mut lines = vec![format!(
+        "{} frame(s), played {}",
+        animation.frames.len(),
+        plays
+    )];
+    for (index, frame) in animation.frames.iter().enumerate() {
+        let control = &frame.control;
+        lines.push(format!(
+            "Frame {}: {}x{} at ({}, {}), delay {:.3}s, dispose {}, blend {}{}",
+            index,
+            control.width,
+            control.height,
+            control.x_offset,
+            control.y_offset,
+            control.delay(),
+            control.dispose_op,
+            control.blend_op,
+            if frame.is_default_image {
+                ", default image"
+            } else {
+                ""
+            }
+        ));
+    }
+    Ok(lines.join("\n"))
+}
+
+pub fn apng_extract_frame(file_path: PathBuf, index: usize, output: PathBuf) -> Result<String> {
+    let frame = apng::extract_frame(&open_png_file(&file_path)?, index)?;
+    write_png_file(&frame, &output)?;
+    Ok(format!("Wrote frame {} to {}", index, output.display()))
+}
+
+pub fn apng_set_loops(file_path: PathBuf, loops: u32) -> Result<String> {
+    // Checking the whole animation first means a broken file is never
+    // rewritten.
+    let control = Animation::parse(&open_png_file(&file_path)?)?.control;
+    let control = AnimationControl {
+        num_plays: loops,
+        ..control
+    };
+    rewrite_png_file(&file_path, &file_path, |writer, chunk| match chunk {
+        Some(chunk) if chunk.chunk_type() == &ChunkType::ACTL => {
+            writer.write_chunk(&control.to_chunk())
+        }
+        Some(chunk) => writer.write_chunk(&chunk),
+        None => Ok(()),
+    })?;
+    Ok(match loops {
+        0 => "Animation now loops forever".into(),
+        loops => format!("Animation now plays {} time(s)", loops),
+    })
+}
+
+/// Compares two files chunk by chunk, returning the report and whether the
+/// files are identical.
+pub fn diff(old_path: PathBuf, new_path: PathBuf, format: OutputFormat) -> Result<(String, bool)> {
+    let diff = diff::diff(&open_png_file(&old_path)?, &open_png_file(&new_path)?)?;
+    let report = match format.structured() {
+        None => diff.to_string(),
+        Some(format) => output::render(&diff, format),
+    };
+    Ok((report, diff.is_empty()))
+}
+
+/// Fixes chunks whose CRC does not match their data and writes the result
+/// to `output_file`, or back to `file_path`.
+pub fn repair(
+    file_path: PathBuf,
+    output_file: Option<PathBuf>,
+    correct_bits: bool,
+    format: OutputFormat,
+) -> Result<String> {
+    let (png, errors) = Png::parse_lenient(&fs::read(&file_path)?)?;
+    let (repaired, fixes) = repair::repair(&png, &errors, correct_bits);
+    if !fixes.is_empty() {
+        write_png_file(&repaired, &output_file.unwrap_or(file_path))?;
+    }
+    Ok(match format.structured() {
+        Some(format) => output::render(&fixes, format),
+        None if fixes.is_empty() => "No CRC errors found".into(),
+        None => {
+            let lines: Vec<String> = fixes.iter().map(|fix| fix.to_string()).collect();
+            lines.join("\n")
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payload::PART_HEADER_LEN;
+
+    #[test]
+    fn test_messages_with_payload_magic_round_trip() {
+        let file_path = temp_file_path("magic.png");
+        let options = [
+            EncodeOptions::default(),
+            EncodeOptions {
+                compression: Some(Compression::Deflate),
+                ..Default::default()
+            },
+            EncodeOptions {
+                max_chunk_size: Some(PART_HEADER_LEN + 4),
+                ..Default::default()
+            },
+        ];
+        for message in [
+            "PMCZ is here",
+            "PMSQ",
+            "PMSL sealed",
+            "PMFL file",
+            "PMRW raw",
+        ] {
+            for options in options.clone() {
+                fs::write(&file_path, Png::from_chunks(vec![]).as_bytes()).unwrap();
+                let input = EncodeInput::Message(message.into());
+                encode(file_path.clone(), "ruSt".into(), input, None, options).unwrap();
+                let decoded =
+                    decode(file_path.clone(), "ruSt".into(), DecodeOptions::default()).unwrap();
+                assert_eq!(decoded, message);
+            }
+        }
+        fs::remove_file(file_path).unwrap();
+    }
+
+    fn chunk_types(file_path: &PathBuf) -> Vec<String> {
+        open_png_file(file_path)
+            .unwrap()
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_encode_without_insertion_point() {
+        let file_path = temp_file_path("no-idat.png");
+        let png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, vec![0; 13]),
+            Chunk::new(ChunkType::IEND, vec![]),
+        ]);
+        fs::write(&file_path, png.as_bytes()).unwrap();
+        let options = EncodeOptions {
+            position: ChunkPosition::AfterIdat,
+            ..Default::default()
+        };
+        let input = EncodeInput::Message("hi".into());
+        encode(file_path.clone(), "ruSt".into(), input, None, options).unwrap();
+        assert_eq!(chunk_types(&file_path), ["IHDR", "ruSt", "IEND"]);
+        fs::remove_file(file_path).unwrap();
+    }
+
+    #[test]
+    fn test_signed_encode_without_insertion_point() {
+        let file_path = temp_file_path("signed-no-idat.png");
+        let secret_path = temp_file_path("signed-no-idat.key");
+        let public_path = temp_file_path("signed-no-idat.pub");
+        let png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, vec![0; 13]),
+            Chunk::new(ChunkType::IEND, vec![]),
+        ]);
+        fs::write(&file_path, png.as_bytes()).unwrap();
+        let (secret, public) = signature::generate_signing_keys();
+        fs::write(&secret_path, crypto::encode_key(&secret)).unwrap();
+        fs::write(&public_path, crypto::encode_key(&public)).unwrap();
+        let options = EncodeOptions {
+            position: ChunkPosition::AfterIdat,
+            sign: Some(secret_path.clone()),
+            ..Default::default()
+        };
+        let input = EncodeInput::Message("hi".into());
+        encode(file_path.clone(), "ruSt".into(), input, None, options).unwrap();
+        assert_eq!(
+            verify(file_path.clone(), public_path.clone()).unwrap(),
+            "ruSt: valid"
+        );
+
+        let (_, other) = signature::generate_signing_keys();
+        fs::write(&public_path, crypto::encode_key(&other)).unwrap();
+        assert!(matches!(
+            verify(file_path.clone(), public_path.clone()),
+            Err(PngError::SignatureInvalid { .. })
+        ));
+        for path in [file_path, secret_path, public_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_encode_into_animation_chunk() {
+        let file_path = temp_file_path("animation.png");
+        let frame = |sequence_number| apng::FrameControl {
+            sequence_number,
+            width: 1,
+            height: 1,
+            x_offset: 0,
+            y_offset: 0,
+            delay_num: 1,
+            delay_den: 10,
+            dispose_op: apng::DisposeOp::None,
+            blend_op: apng::BlendOp::Source,
+        };
+        let png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]),
+            AnimationControl {
+                num_frames: 2,
+                num_plays: 0,
+            }
+            .to_chunk(),
+            frame(0).to_chunk(),
+            Chunk::new(ChunkType::IDAT, vec![]),
+            frame(1).to_chunk(),
+            Chunk::new(ChunkType::FDAT, vec![0, 0, 0, 2]),
+            Chunk::new(ChunkType::IEND, vec![]),
+        ]);
+        fs::write(&file_path, png.as_bytes()).unwrap();
+        for chunk_type in ["fdAT", "fcTL", "acTL"] {
+            let input = EncodeInput::Message("hi".into());
+            let result = encode(
+                file_path.clone(),
+                chunk_type.into(),
+                input,
+                None,
+                EncodeOptions::default(),
+            );
+            assert!(matches!(result, Err(PngError::InvalidAnimation { .. })));
+        }
+        assert_eq!(fs::read(&file_path).unwrap(), png.as_bytes());
+        fs::remove_file(file_path).unwrap();
+    }
+
+    fn temp_file_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("pngme-{}-{}", std::process::id(), name))
+    }
+}
@@ -0,0 +1,154 @@
+use std::io::{Read, Write};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder};
+
+use crate::{error::PngError, Result};
+
+// Compressed payloads start with:
+//
+//   magic (4) | method (1) | compressed data
+//
+// Deflate data is stored in the zlib format, the same one IDAT uses.
+pub(crate) const MAGIC: &[u8; 4] = b"PMCZ";
+const METHOD_DEFLATE: u8 = 1;
+const METHOD_ZSTD: u8 = 2;
+
+/// Largest payload [`decompress`] will produce unless told otherwise.
+pub const DEFAULT_MAX_OUTPUT_SIZE: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Deflate,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+/// Whether `data` looks like a payload produced by [`compress`].
+pub fn is_compressed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Appends `data` to `output` as a zlib stream.
+pub fn deflate(data: &[u8], output: Vec<u8>) -> Result<Vec<u8>> {
+    deflate_with_level(data, output, flate2::Compression::best().level())
+}
+
+/// Like [`deflate`] with a zlib compression level from 0 to 9.
+pub fn deflate_with_level(data: &[u8], output: Vec<u8>, level: u32) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(output, flate2::Compression::new(level.min(9)));
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+/// Inflates a zlib stream, refusing to produce more than `max_output_size`
+/// bytes.
+pub fn inflate(data: &[u8], max_output_size: usize) -> Result<Vec<u8>> {
+    read_limited(ZlibDecoder::new(data), max_output_size)
+}
+
+pub fn compress(data: &[u8], compression: Compression) -> Result<Vec<u8>> {
+    let mut payload = MAGIC.to_vec();
+    match compression {
+        Compression::Deflate => {
+            payload.push(METHOD_DEFLATE);
+            deflate(data, payload)
+        }
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => {
+            payload.push(METHOD_ZSTD);
+            zstd::stream::copy_encode(data, &mut payload, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+            Ok(payload)
+        }
+    }
+}
+
+// Reads at most `max_output_size` bytes, failing rather than truncating when
+// the decoder has more to give.
+fn read_limited<R: Read>(decoder: R, max_output_size: usize) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    decoder
+        .take(max_output_size as u64 + 1)
+        .read_to_end(&mut data)
+        .map_err(|error| PngError::InvalidPayload {
+            reason: format!("compressed data is corrupt: {}", error),
+        })?;
+    if data.len() > max_output_size {
+        return Err(PngError::DecompressionLimit {
+            limit: max_output_size,
+        });
+    }
+    Ok(data)
+}
+
+/// Reverses [`compress`], refusing to produce more than `max_output_size`
+/// bytes so a small payload cannot expand into an enormous one.
+pub fn decompress(payload: &[u8], max_output_size: usize) -> Result<Vec<u8>> {
+    if !is_compressed(payload) || payload.len() <= MAGIC.len() {
+        return Err(PngError::InvalidPayload {
+            reason: "data is not a compressed payload".into(),
+        });
+    }
+    let data = &payload[MAGIC.len() + 1..];
+    match payload[MAGIC.len()] {
+        METHOD_DEFLATE => inflate(data, max_output_size),
+        #[cfg(feature = "zstd")]
+        METHOD_ZSTD => read_limited(zstd::stream::Decoder::new(data)?, max_output_size),
+        #[cfg(not(feature = "zstd"))]
+        METHOD_ZSTD => Err(PngError::InvalidPayload {
+            reason: "payload is zstd compressed but zstd support is not enabled".into(),
+        }),
+        method => Err(PngError::InvalidPayload {
+            reason: format!("unknown compression method {}", method),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &[u8] = b"This is where your secret message will be! \
+        This is where your secret message will be! This is where your secret message will be!";
+
+    #[test]
+    fn test_deflate_round_trip() {
+        let payload = compress(MESSAGE, Compression::Deflate).unwrap();
+        assert!(is_compressed(&payload));
+        assert!(payload.len() < MESSAGE.len());
+        assert_eq!(decompress(&payload, MESSAGE.len()).unwrap(), MESSAGE);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_round_trip() {
+        let payload = compress(MESSAGE, Compression::Zstd).unwrap();
+        assert_eq!(payload[MAGIC.len()], METHOD_ZSTD);
+        assert_eq!(decompress(&payload, MESSAGE.len()).unwrap(), MESSAGE);
+    }
+
+    #[test]
+    fn test_output_limit() {
+        let bomb = compress(&vec![0; 1 << 20], Compression::Deflate).unwrap();
+        assert!(bomb.len() < 2048);
+        assert!(matches!(
+            decompress(&bomb, 1000),
+            Err(PngError::DecompressionLimit { limit: 1000 })
+        ));
+        assert_eq!(decompress(&bomb, 1 << 20).unwrap().len(), 1 << 20);
+    }
+
+    #[test]
+    fn test_corrupt_payload() {
+        let mut payload = compress(MESSAGE, Compression::Deflate).unwrap();
+        let len = payload.len();
+        payload[len - 6] ^= 0xff;
+        assert!(matches!(
+            decompress(&payload, 1000),
+            Err(PngError::InvalidPayload { .. })
+        ));
+
+        payload[MAGIC.len()] = 9;
+        assert!(decompress(&payload, 1000).is_err());
+        assert!(decompress(MAGIC, 1000).is_err());
+    }
+}
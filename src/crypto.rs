@@ -0,0 +1,336 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use crypto_box::{PublicKey, SecretKey};
+
+use crate::{error::PngError, Result};
+
+// Sealed payloads start with this header so they can be told apart from
+// plain messages and so the format can change later:
+//
+//   magic (4) | version (1) | scheme (1) | scheme specific fields | ciphertext
+//
+// Passphrase payloads store the salt and the Argon2 memory, time and
+// parallelism costs (4 bytes each), so changing the defaults never breaks
+// old files, then the nonce. They authenticate the whole header as
+// associated data. Recipient payloads are libsodium sealed boxes, which
+// carry their own ephemeral public key and take no associated data.
+pub(crate) const MAGIC: &[u8; 4] = b"PMSL";
+const VERSION: u8 = 2;
+const SCHEME_PASSPHRASE: u8 = 1;
+const SCHEME_RECIPIENT: u8 = 2;
+
+pub const KEY_LEN: usize = 32;
+
+const SALT_LEN: usize = 16;
+const COSTS_LEN: usize = 12;
+const NONCE_LEN: usize = 12;
+
+// Version 1 passphrase payloads did not store their costs; these are the
+// Argon2 defaults they were written with.
+const VERSION_1_COSTS: [u32; 3] = [19 * 1024, 2, 1];
+
+// Costs read from a file are capped so a hostile one cannot make decoding
+// take gigabytes of memory or minutes of work.
+const MAX_MEMORY_COST: u32 = 1024 * 1024;
+const MAX_TIME_COST: u32 = 64;
+const MAX_PARALLELISM: u32 = 16;
+const PREFIX_LEN: usize = MAGIC.len() + 2;
+
+/// Whether `data` looks like a payload produced by this module.
+pub fn is_sealed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+fn invalid(reason: &str) -> PngError {
+    PngError::InvalidPayload {
+        reason: reason.into(),
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], params: Params) -> Result<Key> {
+    let mut key = Key::default();
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|error| PngError::InvalidPayload {
+            reason: format!("key derivation failed: {}", error),
+        })?;
+    Ok(key)
+}
+
+// Splits a sealed payload into its version, its scheme byte and the bytes
+// after the prefix.
+fn parse_prefix(payload: &[u8]) -> Result<(u8, u8, &[u8])> {
+    if !is_sealed(payload) || payload.len() < PREFIX_LEN {
+        return Err(invalid("data is not an encrypted payload"));
+    }
+    let version = payload[MAGIC.len()];
+    if !(1..=VERSION).contains(&version) {
+        return Err(PngError::InvalidPayload {
+            reason: format!("unsupported payload version {}", version),
+        });
+    }
+    Ok((version, payload[MAGIC.len() + 1], &payload[PREFIX_LEN..]))
+}
+
+fn argon2_params([memory, time, parallelism]: [u32; 3]) -> Result<Params> {
+    if memory > MAX_MEMORY_COST || time > MAX_TIME_COST || parallelism > MAX_PARALLELISM {
+        return Err(invalid("key derivation costs are too high"));
+    }
+    Params::new(memory, time, parallelism, None).map_err(|error| PngError::InvalidPayload {
+        reason: format!("key derivation costs are invalid: {}", error),
+    })
+}
+
+/// Encrypts `plaintext` with ChaCha20-Poly1305 under a key derived from
+/// `passphrase` with Argon2id.
+pub fn encrypt_with_passphrase(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let mut salt = [0; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let params = Params::default();
+    let key = derive_key(passphrase, &salt, params.clone())?;
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let mut payload = MAGIC.to_vec();
+    payload.push(VERSION);
+    payload.push(SCHEME_PASSPHRASE);
+    payload.extend_from_slice(&salt);
+    for cost in [params.m_cost(), params.t_cost(), params.p_cost()] {
+        payload.extend_from_slice(&cost.to_be_bytes());
+    }
+    payload.extend_from_slice(&nonce);
+    let ciphertext = ChaCha20Poly1305::new(&key)
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad: &payload,
+            },
+        )
+        .map_err(|_| invalid("encryption failed"))?;
+    payload.extend_from_slice(&ciphertext);
+    Ok(payload)
+}
+
+/// Reverses [`encrypt_with_passphrase`]. A wrong passphrase and tampered data
+/// both fail with [`PngError::DecryptionFailed`].
+pub fn decrypt_with_passphrase(payload: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let (version, scheme, rest) = parse_prefix(payload)?;
+    if scheme != SCHEME_PASSPHRASE {
+        return Err(invalid("payload is not passphrase encrypted"));
+    }
+    let costs_len = if version == 1 { 0 } else { COSTS_LEN };
+    if rest.len() < SALT_LEN + costs_len + NONCE_LEN {
+        return Err(invalid("encrypted payload is truncated"));
+    }
+    let (salt, rest) = rest.split_at(SALT_LEN);
+    let (costs, rest) = rest.split_at(costs_len);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let header = &payload[..payload.len() - ciphertext.len()];
+    let costs = match version {
+        1 => VERSION_1_COSTS,
+        _ => std::array::from_fn(|i| u32::from_be_bytes(costs[i * 4..][..4].try_into().unwrap())),
+    };
+    let key = derive_key(passphrase, salt, argon2_params(costs)?)?;
+    ChaCha20Poly1305::new(&key)
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| PngError::DecryptionFailed)
+}
+
+/// Generates an X25519 key pair, returned as `(secret, public)`.
+pub fn generate_recipient_keys() -> ([u8; KEY_LEN], [u8; KEY_LEN]) {
+    let secret = SecretKey::generate(&mut OsRng);
+    (secret.to_bytes(), secret.public_key().to_bytes())
+}
+
+/// Encrypts `plaintext` so only the holder of the secret key matching
+/// `public_key` can read it.
+pub fn seal_for_recipient(plaintext: &[u8], public_key: &[u8; KEY_LEN]) -> Result<Vec<u8>> {
+    let sealed = PublicKey::from_bytes(*public_key)
+        .seal(&mut OsRng, plaintext)
+        .map_err(|_| invalid("encryption failed"))?;
+    let mut payload = MAGIC.to_vec();
+    payload.push(VERSION);
+    payload.push(SCHEME_RECIPIENT);
+    payload.extend_from_slice(&sealed);
+    Ok(payload)
+}
+
+/// Reverses [`seal_for_recipient`] with the recipient's secret key.
+pub fn open_with_identity(payload: &[u8], secret_key: &[u8; KEY_LEN]) -> Result<Vec<u8>> {
+    let (_, scheme, sealed) = parse_prefix(payload)?;
+    if scheme != SCHEME_RECIPIENT {
+        return Err(invalid("payload is not encrypted to a recipient"));
+    }
+    SecretKey::from_bytes(*secret_key)
+        .unseal(sealed)
+        .map_err(|_| PngError::DecryptionFailed)
+}
+
+/// Formats a key as the single line of hex stored in key files.
+pub fn encode_key(key: &[u8; KEY_LEN]) -> String {
+    hex::encode(key)
+}
+
+pub fn decode_key(text: &str) -> Result<[u8; KEY_LEN]> {
+    let bytes = hex::decode(text.trim()).map_err(|_| PngError::InvalidKey {
+        reason: "key is not valid hex".into(),
+    })?;
+    bytes
+        .try_into()
+        .map_err(|bytes: Vec<u8>| PngError::InvalidKey {
+            reason: format!("expected {} bytes but found {}", KEY_LEN, bytes.len()),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let payload = encrypt_with_passphrase(b"Secret message", "hunter2").unwrap();
+        assert!(is_sealed(&payload));
+        let plaintext = decrypt_with_passphrase(&payload, "hunter2").unwrap();
+        assert_eq!(plaintext, b"Secret message");
+    }
+
+    #[test]
+    fn test_wrong_passphrase() {
+        let payload = encrypt_with_passphrase(b"Secret message", "hunter2").unwrap();
+        let error = decrypt_with_passphrase(&payload, "hunter3").unwrap_err();
+        assert!(matches!(error, PngError::DecryptionFailed));
+    }
+
+    #[test]
+    fn test_tampering() {
+        let payload = encrypt_with_passphrase(b"Secret message", "hunter2").unwrap();
+
+        // Flipping a bit in the ciphertext or in the authenticated header
+        // must both be rejected.
+        for index in [payload.len() - 1, PREFIX_LEN] {
+            let mut tampered = payload.clone();
+            tampered[index] ^= 1;
+            let error = decrypt_with_passphrase(&tampered, "hunter2").unwrap_err();
+            assert!(matches!(error, PngError::DecryptionFailed));
+        }
+    }
+
+    #[test]
+    fn test_recipient_round_trip() {
+        let (secret, public) = generate_recipient_keys();
+        let payload = seal_for_recipient(b"Secret message", &public).unwrap();
+        assert!(is_sealed(&payload));
+        let plaintext = open_with_identity(&payload, &secret).unwrap();
+        assert_eq!(plaintext, b"Secret message");
+    }
+
+    #[test]
+    fn test_wrong_identity() {
+        let (_, public) = generate_recipient_keys();
+        let (other_secret, _) = generate_recipient_keys();
+        let payload = seal_for_recipient(b"Secret message", &public).unwrap();
+        let error = open_with_identity(&payload, &other_secret).unwrap_err();
+        assert!(matches!(error, PngError::DecryptionFailed));
+    }
+
+    #[test]
+    fn test_scheme_mismatch() {
+        let (secret, public) = generate_recipient_keys();
+        let payload = seal_for_recipient(b"Secret message", &public).unwrap();
+        assert!(matches!(
+            decrypt_with_passphrase(&payload, "hunter2"),
+            Err(PngError::InvalidPayload { .. })
+        ));
+
+        let payload = encrypt_with_passphrase(b"Secret message", "hunter2").unwrap();
+        assert!(matches!(
+            open_with_identity(&payload, &secret),
+            Err(PngError::InvalidPayload { .. })
+        ));
+    }
+
+    #[test]
+    fn test_key_encoding() {
+        let (secret, _) = generate_recipient_keys();
+        let text = encode_key(&secret);
+        assert_eq!(decode_key(&format!("{}\n", text)).unwrap(), secret);
+        assert!(decode_key("not hex").is_err());
+        assert!(decode_key("abcd").is_err());
+    }
+
+    #[test]
+    fn test_malformed_payload() {
+        assert!(matches!(
+            decrypt_with_passphrase(b"Secret message", "hunter2"),
+            Err(PngError::InvalidPayload { .. })
+        ));
+
+        let payload = encrypt_with_passphrase(b"Secret message", "hunter2").unwrap();
+        assert!(matches!(
+            decrypt_with_passphrase(&payload[..PREFIX_LEN + 4], "hunter2"),
+            Err(PngError::InvalidPayload { .. })
+        ));
+
+        let mut payload = payload;
+        payload[MAGIC.len()] = VERSION + 1;
+        assert!(matches!(
+            decrypt_with_passphrase(&payload, "hunter2"),
+            Err(PngError::InvalidPayload { .. })
+        ));
+    }
+
+    // Builds a payload the way version 1 did, without the costs.
+    fn version_1_payload(plaintext: &[u8], passphrase: &str) -> Vec<u8> {
+        let salt = [7; SALT_LEN];
+        let params = argon2_params(VERSION_1_COSTS).unwrap();
+        let key = derive_key(passphrase, &salt, params).unwrap();
+        let nonce = [9; NONCE_LEN];
+        let mut payload = MAGIC.to_vec();
+        payload.extend_from_slice(&[1, SCHEME_PASSPHRASE]);
+        payload.extend_from_slice(&salt);
+        payload.extend_from_slice(&nonce);
+        let ciphertext = ChaCha20Poly1305::new(&key)
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad: &payload,
+                },
+            )
+            .unwrap();
+        payload.extend_from_slice(&ciphertext);
+        payload
+    }
+
+    #[test]
+    fn test_stored_costs() {
+        let payload = encrypt_with_passphrase(b"Secret message", "hunter2").unwrap();
+        let costs = &payload[PREFIX_LEN + SALT_LEN..][..COSTS_LEN];
+        let default = Params::default();
+        assert_eq!(&costs[..4], default.m_cost().to_be_bytes());
+        assert_eq!(&costs[4..8], default.t_cost().to_be_bytes());
+        assert_eq!(&costs[8..], default.p_cost().to_be_bytes());
+
+        let old = version_1_payload(b"Old message", "hunter2");
+        assert_eq!(
+            decrypt_with_passphrase(&old, "hunter2").unwrap(),
+            b"Old message"
+        );
+
+        let mut hostile = payload;
+        hostile[PREFIX_LEN + SALT_LEN..][..4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(
+            decrypt_with_passphrase(&hostile, "hunter2"),
+            Err(PngError::InvalidPayload { .. })
+        ));
+    }
+}
@@ -0,0 +1,526 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    hash::Hash,
+};
+
+use serde::Serialize;
+
+use crate::{
+    chunk::Chunk, chunk_type::ChunkType, error::PngError, png::Png, text::TextChunk, Result,
+};
+
+// Largest table, in cells, that finding the chunks both files share in order
+// may use once their common start and end are taken out.
+const MAX_TABLE_CELLS: usize = 1 << 23;
+
+/// One difference between two PNG files. Indexes count chunks from 0 in
+/// the file they refer to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+    Removed {
+        index: usize,
+        chunk_type: ChunkType,
+        length: usize,
+    },
+    Added {
+        index: usize,
+        chunk_type: ChunkType,
+        length: usize,
+    },
+    /// The same chunk at a different place.
+    Moved {
+        old_index: usize,
+        new_index: usize,
+        chunk_type: ChunkType,
+    },
+    /// A chunk whose data changed but whose type did not.
+    Modified {
+        old_index: usize,
+        new_index: usize,
+        chunk_type: ChunkType,
+        old_length: usize,
+        new_length: usize,
+        /// Bytes that differ, counting bytes only one side has.
+        changed_bytes: usize,
+        first_difference: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        old_text: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        new_text: Option<String>,
+    },
+    /// The data after IEND changed.
+    Trailer {
+        old_length: usize,
+        new_length: usize,
+    },
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::Removed {
+                index,
+                chunk_type,
+                length,
+            } => write!(f, "- {} at {} ({} bytes)", chunk_type, index, length),
+            Change::Added {
+                index,
+                chunk_type,
+                length,
+            } => write!(f, "+ {} at {} ({} bytes)", chunk_type, index, length),
+            Change::Moved {
+                old_index,
+                new_index,
+                chunk_type,
+            } => write!(
+                f,
+                "> {} moved from {} to {}",
+                chunk_type, old_index, new_index
+            ),
+            Change::Modified {
+                old_index,
+                new_index,
+                chunk_type,
+                old_length,
+                new_length,
+                changed_bytes,
+                first_difference,
+                old_text,
+                new_text,
+            } => {
+                write!(
+                    f,
+                    "~ {} at {}",
+                    chunk_type,
+                    match old_index == new_index {
+                        true => old_index.to_string(),
+                        false => format!("{} -> {}", old_index, new_index),
+                    }
+                )?;
+                write!(
+                    f,
+                    ": {} -> {} bytes, {} changed from byte {}",
+                    old_length, new_length, changed_bytes, first_difference
+                )?;
+                if let (Some(old_text), Some(new_text)) = (old_text, new_text) {
+                    write!(f, "\n    - {}\n    + {}", old_text, new_text)?;
+                }
+                Ok(())
+            }
+            Change::Trailer {
+                old_length,
+                new_length,
+            } => write!(
+                f,
+                "~ data after IEND: {} -> {} bytes",
+                old_length, new_length
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diff {
+    pub changes: Vec<Change>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl Display for Diff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "Files are identical");
+        }
+        let lines: Vec<String> = self.changes.iter().map(|c| c.to_string()).collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+// Type, length and CRC pick which chunks to compare, but different data can
+// share a CRC, so only the data decides whether two chunks are identical.
+type Key = ([u8; 4], u32, u32);
+
+fn key(chunk: &Chunk) -> Key {
+    (chunk.chunk_type().bytes(), chunk.length(), chunk.crc())
+}
+
+// Numbers the chunks of both files so that identical chunks, and only those,
+// share a number.
+fn identities(old: &[Chunk], new: &[Chunk]) -> (Vec<usize>, Vec<usize>) {
+    let mut candidates: HashMap<Key, Vec<(usize, &Chunk)>> = HashMap::new();
+    let mut ids = Vec::with_capacity(old.len() + new.len());
+    for chunk in old.iter().chain(new) {
+        let bucket = candidates.entry(key(chunk)).or_default();
+        let id = match bucket
+            .iter()
+            .find(|(_, other)| other.data() == chunk.data())
+        {
+            Some((id, _)) => *id,
+            None => {
+                bucket.push((ids.len(), chunk));
+                ids.len()
+            }
+        };
+        ids.push(id);
+    }
+    let new_ids = ids.split_off(old.len());
+    (ids, new_ids)
+}
+
+// Pairs of indexes of a longest common subsequence of identical chunks.
+fn longest_common_subsequence(old: &[usize], new: &[usize]) -> Result<Vec<(usize, usize)>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (middle_old, middle_new) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+    let (n, m) = (middle_old.len(), middle_new.len());
+    if (n + 1).saturating_mul(m + 1) > MAX_TABLE_CELLS {
+        return Err(PngError::Unsupported {
+            reason: format!(
+                "comparing {} chunks with {} chunks needs too much memory",
+                n, m
+            ),
+        });
+    }
+
+    let mut lengths = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[i][j] = match middle_old[i] == middle_new[j] {
+                true => lengths[i + 1][j + 1] + 1,
+                false => lengths[i + 1][j].max(lengths[i][j + 1]),
+            };
+        }
+    }
+    let mut pairs: Vec<(usize, usize)> = (0..prefix).map(|i| (i, i)).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if middle_old[i] == middle_new[j] {
+            pairs.push((prefix + i, prefix + j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs.extend((0..suffix).map(|k| (old.len() - suffix + k, new.len() - suffix + k)));
+    Ok(pairs)
+}
+
+// Matches each chunk still left in `old_left` with the first chunk left in
+// `new_left` with the same key, taking both out of the running.
+fn pair_up<K: Eq + Hash>(
+    old_keys: &[K],
+    new_keys: &[K],
+    old_left: &mut [Option<usize>],
+    new_left: &mut [Option<usize>],
+) -> Vec<(usize, usize)> {
+    let mut waiting: HashMap<&K, VecDeque<usize>> = HashMap::new();
+    for j in new_left.iter().flatten() {
+        waiting.entry(&new_keys[*j]).or_default().push_back(*j);
+    }
+    let mut pairs = Vec::new();
+    for old_slot in old_left.iter_mut() {
+        let Some(i) = *old_slot else { continue };
+        if let Some(j) = waiting
+            .get_mut(&old_keys[i])
+            .and_then(|queue| queue.pop_front())
+        {
+            pairs.push((i, j));
+            new_left[j] = None;
+            *old_slot = None;
+        }
+    }
+    pairs
+}
+
+fn text(chunk: &Chunk) -> Option<String> {
+    TextChunk::try_from(chunk).ok().map(|text| text.to_string())
+}
+
+fn modified(old_index: usize, old: &Chunk, new_index: usize, new: &Chunk) -> Change {
+    let (old_data, new_data) = (old.data(), new.data());
+    let common = old_data.len().min(new_data.len());
+    let differing = old_data
+        .iter()
+        .zip(new_data)
+        .filter(|(a, b)| a != b)
+        .count();
+    let first_difference = old_data
+        .iter()
+        .zip(new_data)
+        .position(|(a, b)| a != b)
+        .unwrap_or(common);
+    let (old_text, new_text) = match (text(old), text(new)) {
+        (Some(old_text), Some(new_text)) => (Some(old_text), Some(new_text)),
+        _ => (None, None),
+    };
+    Change::Modified {
+        old_index,
+        new_index,
+        chunk_type: old.chunk_type().clone(),
+        old_length: old_data.len(),
+        new_length: new_data.len(),
+        changed_bytes: differing + old_data.len().max(new_data.len()) - common,
+        first_difference,
+        old_text,
+        new_text,
+    }
+}
+
+/// Compares the chunks of two files. Identical chunks are matched up in
+/// order first; of what is left, identical chunks count as moved, chunks
+/// of the same type as modified, and the rest as removed or added.
+pub fn diff(old: &Png, new: &Png) -> Result<Diff> {
+    let (old_chunks, new_chunks) = (old.chunks(), new.chunks());
+    let mut old_left: Vec<Option<usize>> = (0..old_chunks.len()).map(Some).collect();
+    let mut new_left: Vec<Option<usize>> = (0..new_chunks.len()).map(Some).collect();
+    let (old_ids, new_ids) = identities(old_chunks, new_chunks);
+    for (i, j) in longest_common_subsequence(&old_ids, &new_ids)? {
+        old_left[i] = None;
+        new_left[j] = None;
+    }
+
+    // Each change is sorted by where it shows up in the new file, falling
+    // back to the old one for removals.
+    let mut changes: Vec<(usize, Change)> = Vec::new();
+    for (old_index, new_index) in pair_up(&old_ids, &new_ids, &mut old_left, &mut new_left) {
+        changes.push((
+            new_index,
+            Change::Moved {
+                old_index,
+                new_index,
+                chunk_type: old_chunks[old_index].chunk_type().clone(),
+            },
+        ));
+    }
+    let chunk_types = |chunks: &[Chunk]| -> Vec<[u8; 4]> {
+        chunks
+            .iter()
+            .map(|chunk| chunk.chunk_type().bytes())
+            .collect()
+    };
+    for (old_index, new_index) in pair_up(
+        &chunk_types(old_chunks),
+        &chunk_types(new_chunks),
+        &mut old_left,
+        &mut new_left,
+    ) {
+        changes.push((
+            new_index,
+            modified(
+                old_index,
+                &old_chunks[old_index],
+                new_index,
+                &new_chunks[new_index],
+            ),
+        ));
+    }
+    for index in old_left.into_iter().flatten() {
+        let chunk = &old_chunks[index];
+        changes.push((
+            index,
+            Change::Removed {
+                index,
+                chunk_type: chunk.chunk_type().clone(),
+                length: chunk.data().len(),
+            },
+        ));
+    }
+    for index in new_left.into_iter().flatten() {
+        let chunk = &new_chunks[index];
+        changes.push((
+            index,
+            Change::Added {
+                index,
+                chunk_type: chunk.chunk_type().clone(),
+                length: chunk.data().len(),
+            },
+        ));
+    }
+    changes.sort_by_key(|(position, _)| *position);
+
+    let mut changes: Vec<Change> = changes.into_iter().map(|(_, change)| change).collect();
+    if old.trailer() != new.trailer() {
+        changes.push(Change::Trailer {
+            old_length: old.trailer().len(),
+            new_length: new.trailer().len(),
+        });
+    }
+    Ok(Diff { changes })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(chunk_type.parse().unwrap(), data.as_bytes().to_vec())
+    }
+
+    fn png(chunks: &[Chunk]) -> Png {
+        Png::from_chunks(chunks.to_vec())
+    }
+
+    #[test]
+    fn test_identical() {
+        let chunks = [chunk("IHDR", "header"), chunk("IEND", "")];
+        let diff = diff(&png(&chunks), &png(&chunks)).unwrap();
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "Files are identical");
+    }
+
+    #[test]
+    fn test_changes() {
+        let old = png(&[
+            chunk("IHDR", "header"),
+            chunk("tIME", "time"),
+            chunk("IDAT", "pixels"),
+            chunk("ruSt", "hidden"),
+            chunk("IEND", ""),
+        ]);
+        let mut new = png(&[
+            chunk("IHDR", "header"),
+            chunk("IDAT", "pixelz"),
+            chunk("tIME", "time"),
+            chunk("tEXt", "Title\0New"),
+            chunk("IEND", ""),
+        ]);
+        new.set_trailer(b"PK".to_vec());
+
+        let diff = diff(&old, &new).unwrap();
+        assert_eq!(
+            diff.changes,
+            vec![
+                Change::Modified {
+                    old_index: 2,
+                    new_index: 1,
+                    chunk_type: "IDAT".parse().unwrap(),
+                    old_length: 6,
+                    new_length: 6,
+                    changed_bytes: 1,
+                    first_difference: 5,
+                    old_text: None,
+                    new_text: None,
+                },
+                Change::Removed {
+                    index: 3,
+                    chunk_type: "ruSt".parse().unwrap(),
+                    length: 6,
+                },
+                Change::Added {
+                    index: 3,
+                    chunk_type: ChunkType::TEXT,
+                    length: 9,
+                },
+                Change::Trailer {
+                    old_length: 0,
+                    new_length: 2,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_moved_and_text() {
+        let old = png(&[
+            chunk("tEXt", "Title\0Old"),
+            chunk("aaAa", "a"),
+            chunk("bbBb", "b"),
+            chunk("ccCc", "c"),
+        ]);
+        let new = png(&[
+            chunk("tEXt", "Title\0Newer"),
+            chunk("ccCc", "c"),
+            chunk("aaAa", "a"),
+            chunk("bbBb", "b"),
+        ]);
+        let diff = diff(&old, &new).unwrap();
+        assert_eq!(diff.changes.len(), 2);
+        assert_eq!(
+            diff.changes[1],
+            Change::Moved {
+                old_index: 3,
+                new_index: 1,
+                chunk_type: "ccCc".parse().unwrap(),
+            }
+        );
+        assert_eq!(
+            diff.to_string().lines().take(3).collect::<Vec<_>>(),
+            vec![
+                "~ tEXt at 0: 9 -> 11 bytes, 5 changed from byte 6",
+                "    - Title: Old",
+                "    + Title: Newer",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let old = png(&[chunk("ruSt", "hidden")]);
+        let new = png(&[]);
+        let json = serde_json::to_string(&diff(&old, &new).unwrap()).unwrap();
+        assert_eq!(
+            json,
+            r#"{"changes":[{"change":"removed","index":0,"chunk_type":"ruSt","length":6}]}"#
+        );
+    }
+
+    #[test]
+    fn test_many_chunks() {
+        let old: Vec<Chunk> = (0..100_000)
+            .map(|i| chunk("IDAT", &i.to_string()))
+            .collect();
+        let mut new = old.clone();
+        new[50_000] = chunk("IDAT", "changed");
+        assert_eq!(diff(&png(&old), &png(&new)).unwrap().changes.len(), 1);
+
+        let new: Vec<Chunk> = (0..5_000)
+            .map(|i| chunk("IDAT", &format!("new {}", i)))
+            .collect();
+        assert!(matches!(
+            diff(&png(&old[..5_000]), &png(&new)),
+            Err(PngError::Unsupported { .. })
+        ));
+    }
+
+    #[test]
+    fn test_crc_collision() {
+        // Flipping the bits of the CRC-32 polynomial, leading term included,
+        // leaves the CRC of data of the same length unchanged.
+        let data = b"hello world".to_vec();
+        let mut forged = data.clone();
+        let polynomial = (1u64 | (0xEDB8_8320u64 << 1)).to_le_bytes();
+        for (byte, mask) in forged.iter_mut().zip(&polynomial[..5]) {
+            *byte ^= mask;
+        }
+        let old = Chunk::new("ruSt".parse().unwrap(), data);
+        let new = Chunk::new("ruSt".parse().unwrap(), forged);
+        assert_eq!(old.crc(), new.crc());
+        assert_ne!(old.data(), new.data());
+
+        let diff = diff(&png(&[old]), &png(&[new])).unwrap();
+        assert!(matches!(
+            diff.changes[..],
+            [Change::Modified {
+                changed_bytes: 5,
+                ..
+            }]
+        ));
+    }
+}
@@ -0,0 +1,313 @@
+use std::{fmt::Display, io};
+
+use crate::{chunk_type::ChunkType, signature::SignatureStatus, validate::Violation};
+
+#[derive(Debug)]
+pub enum PngError {
+    Io(io::Error),
+    InvalidSignature {
+        found: Vec<u8>,
+    },
+    Truncated {
+        offset: usize,
+        needed: usize,
+        available: usize,
+    },
+    InvalidChunkType {
+        offset: Option<usize>,
+        bytes: Vec<u8>,
+    },
+    ChunkLengthMismatch {
+        offset: usize,
+        declared: u32,
+        available: usize,
+    },
+    CrcMismatch {
+        offset: usize,
+        chunk_type: ChunkType,
+        expected: u32,
+        actual: u32,
+    },
+    ChunkNotFound {
+        chunk_type: ChunkType,
+    },
+    InvalidUtf8 {
+        chunk_type: ChunkType,
+    },
+    IndexOutOfBounds {
+        index: usize,
+        len: usize,
+    },
+    Malformed {
+        violations: Vec<Violation>,
+    },
+    InvalidIhdr {
+        reason: String,
+    },
+    InvalidPayload {
+        reason: String,
+    },
+    DecryptionFailed,
+    InvalidKey {
+        reason: String,
+    },
+    /// At least one signature did not verify; holds the outcome for every
+    /// signed chunk type.
+    SignatureInvalid {
+        results: Vec<(ChunkType, SignatureStatus)>,
+    },
+    SignatureMissing,
+    DecompressionLimit {
+        limit: usize,
+    },
+    InvalidText {
+        reason: String,
+    },
+    KeywordNotFound {
+        keyword: String,
+    },
+    InvalidImageData {
+        reason: String,
+    },
+    CapacityExceeded {
+        needed: usize,
+        available: usize,
+    },
+    Unsupported {
+        reason: String,
+    },
+    InvalidAnimation {
+        reason: String,
+    },
+    FrameNotFound {
+        index: usize,
+        frames: usize,
+    },
+}
+
+impl PngError {
+    /// The process exit code for this error. Scripts rely on these, so a new
+    /// variant takes the next unused number and existing codes never change.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            PngError::Io(_) => 3,
+            PngError::InvalidSignature { .. } => 4,
+            PngError::InvalidChunkType { .. } => 5,
+            PngError::ChunkLengthMismatch { .. } => 6,
+            PngError::CrcMismatch { .. } => 7,
+            PngError::ChunkNotFound { .. } => 8,
+            PngError::InvalidUtf8 { .. } => 9,
+            PngError::Truncated { .. } => 10,
+            PngError::IndexOutOfBounds { .. } => 11,
+            PngError::Malformed { .. } => 12,
+            PngError::InvalidIhdr { .. } => 13,
+            PngError::InvalidPayload { .. } => 14,
+            PngError::DecryptionFailed => 15,
+            PngError::InvalidKey { .. } => 16,
+            PngError::SignatureInvalid { .. } => 17,
+            PngError::SignatureMissing => 18,
+            PngError::DecompressionLimit { .. } => 19,
+            PngError::InvalidText { .. } => 20,
+            PngError::KeywordNotFound { .. } => 21,
+            PngError::InvalidImageData { .. } => 22,
+            PngError::CapacityExceeded { .. } => 23,
+            PngError::Unsupported { .. } => 24,
+            PngError::InvalidAnimation { .. } => 25,
+            PngError::FrameNotFound { .. } => 26,
+        }
+    }
+
+    // Chunk parsing reports offsets relative to the chunk; the PNG parser
+    // shifts them so they point into the whole file.
+    pub(crate) fn shift_offset(self, base: usize) -> PngError {
+        match self {
+            PngError::Truncated {
+                offset,
+                needed,
+                available,
+            } => PngError::Truncated {
+                offset: offset + base,
+                needed,
+                available,
+            },
+            PngError::InvalidChunkType { offset, bytes } => PngError::InvalidChunkType {
+                offset: offset.map(|offset| offset + base),
+                bytes,
+            },
+            PngError::ChunkLengthMismatch {
+                offset,
+                declared,
+                available,
+            } => PngError::ChunkLengthMismatch {
+                offset: offset + base,
+                declared,
+                available,
+            },
+            PngError::CrcMismatch {
+                offset,
+                chunk_type,
+                expected,
+                actual,
+            } => PngError::CrcMismatch {
+                offset: offset + base,
+                chunk_type,
+                expected,
+                actual,
+            },
+            error => error,
+        }
+    }
+}
+
+impl Display for PngError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PngError::Io(error) => write!(f, "{}", error),
+            PngError::InvalidSignature { found } => {
+                write!(f, "PNG signature is invalid: found {:?}", found)
+            }
+            PngError::Truncated {
+                offset,
+                needed,
+                available,
+            } => write!(
+                f,
+                "Input is truncated at byte {}: needed {} bytes but only {} are available",
+                offset, needed, available
+            ),
+            PngError::InvalidChunkType { offset, bytes } => {
+                write!(f, "Chunk type {:?}", bytes)?;
+                if let Some(offset) = offset {
+                    write!(f, " at byte {}", offset)?;
+                }
+                write!(f, " must be 4 ASCII letters")
+            }
+            PngError::ChunkLengthMismatch {
+                offset,
+                declared,
+                available,
+            } => write!(
+                f,
+                "Chunk at byte {} declares {} data bytes but only {} are available",
+                offset, declared, available
+            ),
+            PngError::CrcMismatch {
+                offset,
+                chunk_type,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Chunk {} at byte {} has CRC {:#010x} but {:#010x} was calculated",
+                chunk_type, offset, actual, expected
+            ),
+            PngError::ChunkNotFound { chunk_type } => {
+                write!(f, "Chunk type {} not found", chunk_type)
+            }
+            PngError::InvalidUtf8 { chunk_type } => {
+                write!(f, "Chunk {} data is not valid UTF-8", chunk_type)
+            }
+            PngError::IndexOutOfBounds { index, len } => write!(
+                f,
+                "Chunk index {} is out of bounds for a PNG with {} chunks",
+                index, len
+            ),
+            PngError::Malformed { violations } => {
+                write!(f, "PNG is malformed:")?;
+                for violation in violations {
+                    write!(f, "\n  {}", violation)?;
+                }
+                Ok(())
+            }
+            PngError::InvalidIhdr { reason } => write!(f, "IHDR is invalid: {}", reason),
+            PngError::InvalidPayload { reason } => write!(f, "Payload is invalid: {}", reason),
+            PngError::DecryptionFailed => write!(
+                f,
+                "Decryption failed: the key is wrong or the data was tampered with"
+            ),
+            PngError::InvalidKey { reason } => write!(f, "Key is invalid: {}", reason),
+            PngError::SignatureInvalid { results } => {
+                write!(f, "Signatures failed to verify:")?;
+                for (chunk_type, status) in results {
+                    write!(f, "\n  {}: {}", chunk_type, status)?;
+                }
+                Ok(())
+            }
+            PngError::SignatureMissing => write!(f, "PNG has no signature"),
+            PngError::DecompressionLimit { limit } => write!(
+                f,
+                "Payload decompresses to more than the {} byte limit",
+                limit
+            ),
+            PngError::InvalidText { reason } => write!(f, "Text chunk is invalid: {}", reason),
+            PngError::KeywordNotFound { keyword } => {
+                write!(f, "No text chunk with keyword {:?}", keyword)
+            }
+            PngError::InvalidImageData { reason } => write!(f, "Image data is invalid: {}", reason),
+            PngError::CapacityExceeded { needed, available } => write!(
+                f,
+                "Payload needs {} bytes but the image only has room for {}",
+                needed, available
+            ),
+            PngError::Unsupported { reason } => write!(f, "Not supported: {}", reason),
+            PngError::InvalidAnimation { reason } => write!(f, "Animation is invalid: {}", reason),
+            PngError::FrameNotFound { index, frames } => write!(
+                f,
+                "Frame {} does not exist in an animation with {} frames",
+                index, frames
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PngError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PngError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PngError {
+    fn from(error: io::Error) -> Self {
+        PngError::Io(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes_are_stable() {
+        let chunk_type = ChunkType::IHDR;
+        let codes = [
+            (
+                PngError::InvalidChunkType {
+                    offset: None,
+                    bytes: vec![],
+                },
+                5,
+            ),
+            (
+                PngError::ChunkNotFound {
+                    chunk_type: chunk_type.clone(),
+                },
+                8,
+            ),
+            (PngError::InvalidUtf8 { chunk_type }, 9),
+            (
+                PngError::Truncated {
+                    offset: 0,
+                    needed: 12,
+                    available: 0,
+                },
+                10,
+            ),
+        ];
+        for (error, code) in codes {
+            assert_eq!(error.exit_code(), code, "{}", error);
+        }
+    }
+}
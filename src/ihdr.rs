@@ -0,0 +1,262 @@
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, error::PngError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorType {
+    Grayscale,
+    Rgb,
+    Indexed,
+    GrayscaleAlpha,
+    Rgba,
+}
+
+impl ColorType {
+    pub fn value(&self) -> u8 {
+        match self {
+            ColorType::Grayscale => 0,
+            ColorType::Rgb => 2,
+            ColorType::Indexed => 3,
+            ColorType::GrayscaleAlpha => 4,
+            ColorType::Rgba => 6,
+        }
+    }
+
+    /// Number of samples that make up one pixel.
+    pub fn channels(&self) -> usize {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+
+    pub fn allowed_bit_depths(&self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Rgb | ColorType::GrayscaleAlpha | ColorType::Rgba => &[8, 16],
+        }
+    }
+}
+
+impl TryFrom<u8> for ColorType {
+    type Error = crate::Error;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ColorType::Grayscale),
+            2 => Ok(ColorType::Rgb),
+            3 => Ok(ColorType::Indexed),
+            4 => Ok(ColorType::GrayscaleAlpha),
+            6 => Ok(ColorType::Rgba),
+            _ => Err(PngError::InvalidIhdr {
+                reason: format!("unknown color type {}", value),
+            }),
+        }
+    }
+}
+
+impl Display for ColorType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ColorType::Grayscale => "grayscale",
+            ColorType::Rgb => "RGB",
+            ColorType::Indexed => "indexed",
+            ColorType::GrayscaleAlpha => "grayscale with alpha",
+            ColorType::Rgba => "RGBA",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InterlaceMethod {
+    None,
+    Adam7,
+}
+
+impl Display for InterlaceMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InterlaceMethod::None => write!(f, "none"),
+            InterlaceMethod::Adam7 => write!(f, "Adam7"),
+        }
+    }
+}
+
+/// The image header stored in the IHDR chunk.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Ihdr {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    pub compression_method: u8,
+    pub filter_method: u8,
+    pub interlace_method: InterlaceMethod,
+}
+
+fn invalid(reason: String) -> PngError {
+    PngError::InvalidIhdr { reason }
+}
+
+impl TryFrom<&[u8]> for Ihdr {
+    type Error = crate::Error;
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        if value.len() != 13 {
+            return Err(invalid(format!(
+                "expected 13 bytes of data but found {}",
+                value.len()
+            )));
+        }
+        let color_type = ColorType::try_from(value[9])?;
+        let interlace_method = match value[12] {
+            0 => InterlaceMethod::None,
+            1 => InterlaceMethod::Adam7,
+            method => return Err(invalid(format!("unknown interlace method {}", method))),
+        };
+        let ihdr = Ihdr {
+            width: u32::from_be_bytes(value[0..4].try_into().unwrap()),
+            height: u32::from_be_bytes(value[4..8].try_into().unwrap()),
+            bit_depth: value[8],
+            color_type,
+            compression_method: value[10],
+            filter_method: value[11],
+            interlace_method,
+        };
+        ihdr.check()?;
+        Ok(ihdr)
+    }
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+    type Error = crate::Error;
+    fn try_from(value: &Chunk) -> Result<Self, Self::Error> {
+        if value.chunk_type() != &ChunkType::IHDR {
+            return Err(invalid(format!(
+                "expected an IHDR chunk but found {}",
+                value.chunk_type()
+            )));
+        }
+        Ihdr::try_from(value.data())
+    }
+}
+
+impl Ihdr {
+    /// Checks the fields against the rules of the PNG specification. Parsing
+    /// already does this; headers built by hand should be checked before use.
+    pub fn check(&self) -> crate::Result<()> {
+        for (name, dimension) in [("width", self.width), ("height", self.height)] {
+            if dimension == 0 || dimension > i32::MAX as u32 {
+                return Err(invalid(format!("{} {} is out of range", name, dimension)));
+            }
+        }
+        if !self
+            .color_type
+            .allowed_bit_depths()
+            .contains(&self.bit_depth)
+        {
+            return Err(invalid(format!(
+                "bit depth {} is not allowed for {} images",
+                self.bit_depth, self.color_type
+            )));
+        }
+        if self.compression_method != 0 {
+            return Err(invalid(format!(
+                "unknown compression method {}",
+                self.compression_method
+            )));
+        }
+        if self.filter_method != 0 {
+            return Err(invalid(format!(
+                "unknown filter method {}",
+                self.filter_method
+            )));
+        }
+        Ok(())
+    }
+
+    /// Bits used by a single pixel.
+    pub fn bits_per_pixel(&self) -> usize {
+        self.color_type.channels() * self.bit_depth as usize
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = Vec::with_capacity(13);
+        data.extend_from_slice(&self.width.to_be_bytes());
+        data.extend_from_slice(&self.height.to_be_bytes());
+        data.extend_from_slice(&[
+            self.bit_depth,
+            self.color_type.value(),
+            self.compression_method,
+            self.filter_method,
+            match self.interlace_method {
+                InterlaceMethod::None => 0,
+                InterlaceMethod::Adam7 => 1,
+            },
+        ]);
+        Chunk::new(ChunkType::IHDR, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ihdr_bytes(width: u32, height: u32, bit_depth: u8, color_type: u8) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&width.to_be_bytes());
+        bytes.extend_from_slice(&height.to_be_bytes());
+        bytes.extend_from_slice(&[bit_depth, color_type, 0, 0, 1]);
+        bytes
+    }
+
+    #[test]
+    fn test_parse_ihdr() {
+        let ihdr = Ihdr::try_from(ihdr_bytes(50, 40, 8, 6).as_slice()).unwrap();
+        assert_eq!(ihdr.width, 50);
+        assert_eq!(ihdr.height, 40);
+        assert_eq!(ihdr.bit_depth, 8);
+        assert_eq!(ihdr.color_type, ColorType::Rgba);
+        assert_eq!(ihdr.interlace_method, InterlaceMethod::Adam7);
+        assert_eq!(ihdr.bits_per_pixel(), 32);
+    }
+
+    #[test]
+    fn test_parse_ihdr_chunk() {
+        let chunk = Chunk::new(ChunkType::IHDR, ihdr_bytes(1, 1, 1, 0));
+        let ihdr = Ihdr::try_from(&chunk).unwrap();
+        assert_eq!(ihdr.color_type, ColorType::Grayscale);
+    }
+
+    #[test]
+    fn test_to_chunk() {
+        let chunk = Chunk::new(ChunkType::IHDR, ihdr_bytes(50, 40, 16, 4));
+        let ihdr = Ihdr::try_from(&chunk).unwrap();
+        assert_eq!(ihdr.to_chunk().as_bytes(), chunk.as_bytes());
+    }
+
+    #[test]
+    fn test_illegal_bit_depth() {
+        assert!(Ihdr::try_from(ihdr_bytes(1, 1, 4, 2).as_slice()).is_err());
+        assert!(Ihdr::try_from(ihdr_bytes(1, 1, 16, 3).as_slice()).is_err());
+        assert!(Ihdr::try_from(ihdr_bytes(1, 1, 3, 0).as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_illegal_values() {
+        assert!(Ihdr::try_from(ihdr_bytes(0, 1, 8, 0).as_slice()).is_err());
+        assert!(Ihdr::try_from(ihdr_bytes(1, u32::MAX, 8, 0).as_slice()).is_err());
+        assert!(Ihdr::try_from(ihdr_bytes(1, 1, 8, 5).as_slice()).is_err());
+        assert!(Ihdr::try_from(&ihdr_bytes(1, 1, 8, 0)[..12]).is_err());
+
+        let mut bytes = ihdr_bytes(1, 1, 8, 0);
+        bytes[12] = 2;
+        assert!(Ihdr::try_from(bytes.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,726 @@
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    compress,
+    error::PngError,
+    ihdr::{ColorType, Ihdr, InterlaceMethod},
+    png::Png,
+    Result,
+};
+
+// Origin and spacing of the pixels in each of the seven Adam7 passes.
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+// Largest IDAT chunk written by the encoder, the same size libpng uses.
+const IDAT_CHUNK_SIZE: usize = 8192;
+
+/// Largest image, in bytes of samples or of filtered scanlines, that will be
+/// decoded or encoded. This also bounds what a zlib bomb in IDAT can inflate
+/// to.
+pub const MAX_IMAGE_SIZE: usize = 256 * 1024 * 1024;
+
+/// How the encoder picks a filter type for each scanline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterStrategy {
+    /// Leave every scanline unfiltered.
+    None,
+    /// Pick the filter with the smallest sum of absolute differences, as
+    /// libpng does. Indexed and sub-byte images are left unfiltered, as the
+    /// spec recommends.
+    Heuristic,
+    /// Deflate every scanline with each filter and keep the smallest.
+    BruteForce,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodingOptions {
+    pub filter: FilterStrategy,
+    /// zlib compression level, from 0 (none) to 9 (best).
+    pub compression_level: u32,
+}
+
+impl Default for EncodingOptions {
+    fn default() -> Self {
+        EncodingOptions {
+            filter: FilterStrategy::Heuristic,
+            compression_level: 6,
+        }
+    }
+}
+
+/// The pixels of a PNG after undoing compression, filtering and interlacing.
+///
+/// Samples are stored row by row, one byte each for bit depths up to 8 and two
+/// big-endian bytes each for 16-bit images. Samples narrower than a byte are
+/// unpacked but not rescaled, and indexed images hold palette indices.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageData {
+    ihdr: Ihdr,
+    data: Vec<u8>,
+}
+
+fn invalid(reason: String) -> PngError {
+    PngError::InvalidImageData { reason }
+}
+
+// Bytes in one filtered scanline of `width` pixels, without the filter byte.
+fn scanline_len(ihdr: &Ihdr, width: usize) -> usize {
+    (width * ihdr.bits_per_pixel()).div_ceil(8)
+}
+
+// Bytes of filtered scanlines, filter bytes included, and bytes of samples of
+// an image described by `ihdr`, failing if either is over MAX_IMAGE_SIZE.
+pub(crate) fn image_sizes(ihdr: &Ihdr) -> Result<(usize, usize)> {
+    let too_large = || {
+        invalid(format!(
+            "a {}x{} image is larger than the {} byte limit",
+            ihdr.width, ihdr.height, MAX_IMAGE_SIZE
+        ))
+    };
+    let bytes_per_sample = if ihdr.bit_depth == 16 { 2 } else { 1 };
+    let samples = (ihdr.width as usize)
+        .checked_mul(ihdr.height as usize)
+        .and_then(|pixels| pixels.checked_mul(ihdr.color_type.channels() * bytes_per_sample))
+        .filter(|&size| size <= MAX_IMAGE_SIZE)
+        .ok_or_else(too_large)?;
+    let filtered = passes(ihdr)
+        .iter()
+        .try_fold(0usize, |total, &(.., width, height)| {
+            let len = width.checked_mul(ihdr.bits_per_pixel())?.div_ceil(8);
+            height.checked_mul(1 + len)?.checked_add(total)
+        })
+        .filter(|&size| size <= MAX_IMAGE_SIZE)
+        .ok_or_else(too_large)?;
+    Ok((filtered, samples))
+}
+
+// The pixel grid of every non-empty pass: (x0, y0, dx, dy, width, height).
+fn passes(ihdr: &Ihdr) -> Vec<(usize, usize, usize, usize, usize, usize)> {
+    let (width, height) = (ihdr.width as usize, ihdr.height as usize);
+    let grids: &[(usize, usize, usize, usize)] = match ihdr.interlace_method {
+        InterlaceMethod::None => &[(0, 0, 1, 1)],
+        InterlaceMethod::Adam7 => &ADAM7,
+    };
+    grids
+        .iter()
+        .map(|&(x0, y0, dx, dy)| {
+            let pass_width = width.saturating_sub(x0).div_ceil(dx);
+            let pass_height = height.saturating_sub(y0).div_ceil(dy);
+            (x0, y0, dx, dy, pass_width, pass_height)
+        })
+        .filter(|&(.., pass_width, pass_height)| pass_width > 0 && pass_height > 0)
+        .collect()
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// Reverses the filter on `row` in place. `previous` is the unfiltered row above
+// it, or all zeros for the first row of a pass.
+fn unfilter(filter_type: u8, row: &mut [u8], previous: &[u8], bpp: usize) -> Result<()> {
+    match filter_type {
+        0 => {}
+        1 => {
+            for i in bpp..row.len() {
+                row[i] = row[i].wrapping_add(row[i - bpp]);
+            }
+        }
+        2 => {
+            for (x, b) in row.iter_mut().zip(previous) {
+                *x = x.wrapping_add(*b);
+            }
+        }
+        3 => {
+            for i in 0..row.len() {
+                let a = if i >= bpp { row[i - bpp] } else { 0 };
+                let average = ((a as u16 + previous[i] as u16) / 2) as u8;
+                row[i] = row[i].wrapping_add(average);
+            }
+        }
+        4 => {
+            for i in 0..row.len() {
+                let (a, c) = match i >= bpp {
+                    true => (row[i - bpp], previous[i - bpp]),
+                    false => (0, 0),
+                };
+                row[i] = row[i].wrapping_add(paeth(a, previous[i], c));
+            }
+        }
+        filter_type => return Err(invalid(format!("unknown filter type {}", filter_type))),
+    }
+    Ok(())
+}
+
+// Applies `filter_type` to `row`, writing the filter byte and the filtered
+// bytes to `output`.
+fn filter(filter_type: u8, row: &[u8], previous: &[u8], bpp: usize, output: &mut Vec<u8>) {
+    output.push(filter_type);
+    for i in 0..row.len() {
+        let (a, c) = match i >= bpp {
+            true => (row[i - bpp], previous[i - bpp]),
+            false => (0, 0),
+        };
+        let b = previous[i];
+        let predictor = match filter_type {
+            0 => 0,
+            1 => a,
+            2 => b,
+            3 => ((a as u16 + b as u16) / 2) as u8,
+            _ => paeth(a, b, c),
+        };
+        output.push(row[i].wrapping_sub(predictor));
+    }
+}
+
+fn filter_row(
+    options: &EncodingOptions,
+    ihdr: &Ihdr,
+    row: &[u8],
+    previous: &[u8],
+    output: &mut Vec<u8>,
+) -> Result<()> {
+    let bpp = ihdr.bits_per_pixel().div_ceil(8);
+    let unfiltered = ihdr.color_type == ColorType::Indexed || ihdr.bit_depth < 8;
+    let filter_type = match options.filter {
+        FilterStrategy::None => 0,
+        FilterStrategy::Heuristic if unfiltered => 0,
+        FilterStrategy::Heuristic => (0..5)
+            .min_by_key(|&filter_type| {
+                let mut candidate = Vec::with_capacity(row.len() + 1);
+                filter(filter_type, row, previous, bpp, &mut candidate);
+                candidate[1..]
+                    .iter()
+                    .map(|&byte| (byte as i8).unsigned_abs() as usize)
+                    .sum::<usize>()
+            })
+            .unwrap(),
+        FilterStrategy::BruteForce => {
+            let mut best = (usize::MAX, 0);
+            for filter_type in 0..5 {
+                let mut candidate = Vec::with_capacity(row.len() + 1);
+                filter(filter_type, row, previous, bpp, &mut candidate);
+                let size = compress::deflate_with_level(
+                    &candidate,
+                    Vec::new(),
+                    options.compression_level,
+                )?
+                .len();
+                best = best.min((size, filter_type));
+            }
+            best.1
+        }
+    };
+    filter(filter_type, row, previous, bpp, output);
+    Ok(())
+}
+
+impl ImageData {
+    /// Wraps samples laid out as described on [`ImageData`] so they can be
+    /// encoded.
+    pub fn new(ihdr: Ihdr, data: Vec<u8>) -> Result<ImageData> {
+        ihdr.check()?;
+        let (_, expected) = image_sizes(&ihdr)?;
+        if data.len() != expected {
+            return Err(invalid(format!(
+                "expected {} bytes of samples but found {}",
+                expected,
+                data.len()
+            )));
+        }
+        if ihdr.bit_depth < 8 {
+            let max = (1 << ihdr.bit_depth) - 1;
+            if let Some(sample) = data.iter().find(|&&sample| sample > max) {
+                return Err(invalid(format!(
+                    "sample {} does not fit in {} bits",
+                    sample, ihdr.bit_depth
+                )));
+            }
+        }
+        Ok(ImageData { ihdr, data })
+    }
+
+    /// Decodes the image data of `png`.
+    pub fn decode(png: &Png) -> Result<ImageData> {
+        let ihdr = png.header_info()?;
+        let compressed: Vec<u8> = png
+            .chunks()
+            .iter()
+            .filter(|chunk| chunk.chunk_type() == &ChunkType::IDAT)
+            .flat_map(|chunk| chunk.data().iter().copied())
+            .collect();
+        if compressed.is_empty() {
+            return Err(PngError::ChunkNotFound {
+                chunk_type: ChunkType::IDAT,
+            });
+        }
+        ImageData::from_zlib(ihdr, &compressed)
+    }
+
+    /// Decodes the concatenated contents of the IDAT chunks of an image
+    /// described by `ihdr`.
+    pub fn from_zlib(ihdr: Ihdr, compressed: &[u8]) -> Result<ImageData> {
+        ihdr.check()?;
+        let (expected, samples) = image_sizes(&ihdr)?;
+        let filtered = compress::inflate(compressed, expected).map_err(|error| match error {
+            PngError::DecompressionLimit { .. } => {
+                invalid(format!("image data is longer than {} bytes", expected))
+            }
+            PngError::InvalidPayload { reason } => invalid(reason),
+            error => error,
+        })?;
+        if filtered.len() < expected {
+            return Err(invalid(format!(
+                "image data should be {} bytes but found {}",
+                expected,
+                filtered.len()
+            )));
+        }
+
+        let mut data = vec![0; samples];
+        let bpp = ihdr.bits_per_pixel().div_ceil(8);
+
+        let mut offset = 0;
+        for (x0, y0, dx, dy, width, height) in passes(&ihdr) {
+            let len = scanline_len(&ihdr, width);
+            let mut previous = vec![0; len];
+            for row_index in 0..height {
+                let filter_type = filtered[offset];
+                let mut row = filtered[offset + 1..offset + 1 + len].to_vec();
+                offset += 1 + len;
+                unfilter(filter_type, &mut row, &previous, bpp)?;
+
+                unpack_row(&ihdr, &row, width, &mut data, x0, dx, y0 + row_index * dy);
+                previous = row;
+            }
+        }
+        Ok(ImageData { ihdr, data })
+    }
+
+    /// Filters and deflates the samples into IDAT chunks.
+    pub fn encode(&self, options: &EncodingOptions) -> Result<Vec<Chunk>> {
+        let ihdr = &self.ihdr;
+        let mut filtered = Vec::new();
+        for (x0, y0, dx, dy, width, height) in passes(ihdr) {
+            let len = scanline_len(ihdr, width);
+            let mut previous = vec![0; len];
+            for row_index in 0..height {
+                let row = pack_row(ihdr, &self.data, width, x0, dx, y0 + row_index * dy);
+                filter_row(options, ihdr, &row, &previous, &mut filtered)?;
+                previous = row;
+            }
+        }
+        let compressed =
+            compress::deflate_with_level(&filtered, Vec::new(), options.compression_level)?;
+        Ok(compressed
+            .chunks(IDAT_CHUNK_SIZE)
+            .map(|data| Chunk::new(ChunkType::IDAT, data.to_vec()))
+            .collect())
+    }
+
+    /// Encodes the samples into a complete PNG. `chunks` are placed between
+    /// IHDR and the image data, which is where PLTE and the chunks that
+    /// describe colors belong.
+    pub fn to_png(&self, options: &EncodingOptions, chunks: Vec<Chunk>) -> Result<Png> {
+        let mut all_chunks = vec![self.ihdr.to_chunk()];
+        all_chunks.extend(chunks);
+        all_chunks.extend(self.encode(options)?);
+        all_chunks.push(Chunk::new(ChunkType::IEND, Vec::new()));
+        Ok(Png::from_chunks(all_chunks))
+    }
+
+    pub fn ihdr(&self) -> &Ihdr {
+        &self.ihdr
+    }
+
+    pub fn width(&self) -> u32 {
+        self.ihdr.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.ihdr.height
+    }
+
+    pub fn bytes_per_sample(&self) -> usize {
+        if self.ihdr.bit_depth == 16 {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// All samples, row by row.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// The samples of row `y`.
+    pub fn row(&self, y: u32) -> &[u8] {
+        let stride = self.data.len() / self.ihdr.height as usize;
+        &self.data[y as usize * stride..][..stride]
+    }
+
+    /// The value of one sample.
+    pub fn sample(&self, x: u32, y: u32, channel: usize) -> u16 {
+        let channels = self.ihdr.color_type.channels();
+        let index = (x as usize * channels + channel) * self.bytes_per_sample();
+        let row = self.row(y);
+        match self.bytes_per_sample() {
+            2 => u16::from_be_bytes([row[index], row[index + 1]]),
+            _ => row[index] as u16,
+        }
+    }
+}
+
+// Copies the pixels of an unfiltered scanline into `data`, which uses the
+// layout of ImageData, starting at (`x0`, `y`) and `dx` pixels apart.
+fn unpack_row(
+    ihdr: &Ihdr,
+    row: &[u8],
+    width: usize,
+    data: &mut [u8],
+    x0: usize,
+    dx: usize,
+    y: usize,
+) {
+    let bit_depth = ihdr.bit_depth as usize;
+    let pixel_len = match bit_depth {
+        16 => ihdr.color_type.channels() * 2,
+        _ => ihdr.color_type.channels(),
+    };
+    let stride = ihdr.width as usize * pixel_len;
+    for column in 0..width {
+        let start = y * stride + (x0 + column * dx) * pixel_len;
+        if bit_depth >= 8 {
+            data[start..start + pixel_len].copy_from_slice(&row[column * pixel_len..][..pixel_len]);
+        } else {
+            // Only single channel images have samples narrower than a byte.
+            let bit = column * bit_depth;
+            let shift = 8 - bit_depth - bit % 8;
+            data[start] = (row[bit / 8] >> shift) & ((1 << bit_depth) - 1);
+        }
+    }
+}
+
+// The reverse of unpack_row: gathers pixels from `data` into a scanline.
+fn pack_row(ihdr: &Ihdr, data: &[u8], width: usize, x0: usize, dx: usize, y: usize) -> Vec<u8> {
+    let bit_depth = ihdr.bit_depth as usize;
+    let pixel_len = match bit_depth {
+        16 => ihdr.color_type.channels() * 2,
+        _ => ihdr.color_type.channels(),
+    };
+    let stride = ihdr.width as usize * pixel_len;
+    let mut row = vec![0; scanline_len(ihdr, width)];
+    for column in 0..width {
+        let start = y * stride + (x0 + column * dx) * pixel_len;
+        if bit_depth >= 8 {
+            row[column * pixel_len..][..pixel_len].copy_from_slice(&data[start..start + pixel_len]);
+        } else {
+            let bit = column * bit_depth;
+            row[bit / 8] |= data[start] << (8 - bit_depth - bit % 8);
+        }
+    }
+    row
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::ColorType;
+    use std::{fs, path::PathBuf};
+
+    // Mirrors sample() in testdata/generate.py.
+    fn expected_sample(x: u32, y: u32, channel: usize, bit_depth: u8) -> u16 {
+        let (x, y, channel) = (x as u64, y as u64, channel as u64);
+        match bit_depth {
+            16 => ((x * 1031 + y * 4099 + channel * 7433) % 65536) as u16,
+            _ => ((x * 7 + y * 13 + channel * 29 + 3) % (1 << bit_depth)) as u16,
+        }
+    }
+
+    fn reference_images() -> Vec<PathBuf> {
+        let directory = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("testdata/images");
+        let mut paths: Vec<PathBuf> = fs::read_dir(directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    fn decode_file(path: &PathBuf) -> ImageData {
+        let png = Png::try_from(fs::read(path).unwrap().as_slice()).unwrap();
+        ImageData::decode(&png).unwrap()
+    }
+
+    #[test]
+    fn test_reference_images() {
+        let paths = reference_images();
+        assert_eq!(paths.len(), 33);
+        for path in paths {
+            let image = decode_file(&path);
+            let ihdr = image.ihdr().clone();
+            for y in 0..ihdr.height {
+                for x in 0..ihdr.width {
+                    for channel in 0..ihdr.color_type.channels() {
+                        assert_eq!(
+                            image.sample(x, y, channel),
+                            expected_sample(x, y, channel, ihdr.bit_depth),
+                            "{} at ({}, {}) channel {}",
+                            path.display(),
+                            x,
+                            y,
+                            channel
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_interlaced_matches_plain() {
+        for path in reference_images() {
+            let name = path.file_name().unwrap().to_str().unwrap();
+            if name.contains("n_") {
+                let interlaced = path.with_file_name(name.replace("n_", "i_"));
+                assert_eq!(
+                    decode_file(&path).data(),
+                    decode_file(&interlaced).data(),
+                    "{}",
+                    name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_layout() {
+        let path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("testdata/images/c6d16n_13x11.png");
+        let image = decode_file(&path);
+        assert_eq!(image.ihdr().color_type, ColorType::Rgba);
+        assert_eq!(image.bytes_per_sample(), 2);
+        assert_eq!(image.data().len(), 13 * 11 * 4 * 2);
+        assert_eq!(image.row(1).len(), 13 * 4 * 2);
+    }
+
+    #[test]
+    fn test_matches_png_crate() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("rabbit.png");
+        let decoder = png::Decoder::new(fs::File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut expected = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut expected).unwrap();
+        assert_eq!(decode_file(&path).data(), expected.as_slice());
+    }
+
+    fn palette_chunk(image: &ImageData) -> Vec<Chunk> {
+        match image.ihdr().color_type {
+            ColorType::Indexed => vec![Chunk::new(
+                ChunkType::PLTE,
+                vec![0; 3 << image.ihdr().bit_depth],
+            )],
+            _ => vec![],
+        }
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        for path in reference_images() {
+            let image = decode_file(&path);
+            for filter in [
+                FilterStrategy::None,
+                FilterStrategy::Heuristic,
+                FilterStrategy::BruteForce,
+            ] {
+                let options = EncodingOptions {
+                    filter,
+                    compression_level: 9,
+                };
+                let png = image.to_png(&options, palette_chunk(&image)).unwrap();
+                assert_eq!(png.validate(), vec![], "{}", path.display());
+                let bytes = png.as_bytes();
+                let decoded = ImageData::decode(&Png::try_from(bytes.as_slice()).unwrap()).unwrap();
+                assert_eq!(decoded, image, "{} with {:?}", path.display(), filter);
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_matches_png_crate() {
+        let ihdr = Ihdr {
+            width: 64,
+            height: 48,
+            bit_depth: 8,
+            color_type: ColorType::Rgb,
+            compression_method: 0,
+            filter_method: 0,
+            interlace_method: InterlaceMethod::Adam7,
+        };
+        let data: Vec<u8> = (0..64 * 48 * 3).map(|i| (i * i / 7) as u8).collect();
+        let image = ImageData::new(ihdr, data.clone()).unwrap();
+        let bytes = image
+            .to_png(&EncodingOptions::default(), vec![])
+            .unwrap()
+            .as_bytes();
+
+        let mut reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+        let mut decoded = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn test_compression_level() {
+        let ihdr = Ihdr {
+            width: 256,
+            height: 64,
+            bit_depth: 8,
+            color_type: ColorType::Grayscale,
+            compression_method: 0,
+            filter_method: 0,
+            interlace_method: InterlaceMethod::None,
+        };
+        let image = ImageData::new(ihdr, (0..256 * 64).map(|i| i as u8).collect()).unwrap();
+        let size = |compression_level| {
+            let options = EncodingOptions {
+                filter: FilterStrategy::None,
+                compression_level,
+            };
+            let chunks = image.encode(&options).unwrap();
+            assert!(chunks
+                .iter()
+                .all(|chunk| chunk.data().len() <= IDAT_CHUNK_SIZE));
+            chunks.iter().map(|chunk| chunk.data().len()).sum::<usize>()
+        };
+        assert!(size(1) < size(0));
+    }
+
+    #[test]
+    fn test_new_rejects_bad_samples() {
+        let ihdr = decode_file(&reference_images()[0]).ihdr().clone();
+        let ihdr = Ihdr {
+            bit_depth: 2,
+            color_type: ColorType::Grayscale,
+            ..ihdr
+        };
+        let len = (ihdr.width * ihdr.height) as usize;
+        assert!(ImageData::new(ihdr.clone(), vec![3; len]).is_ok());
+        assert!(ImageData::new(ihdr.clone(), vec![4; len]).is_err());
+        assert!(ImageData::new(ihdr, vec![0; len - 1]).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_image_data() {
+        let path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("testdata/images/c2d8n_13x11.png");
+        let png = Png::try_from(fs::read(path).unwrap().as_slice()).unwrap();
+        let ihdr = png.header_info().unwrap();
+
+        let filtered = vec![0; 11 * (1 + 13 * 3)];
+        let compressed = compress::deflate(&filtered, Vec::new()).unwrap();
+        assert!(ImageData::from_zlib(ihdr.clone(), &compressed).is_ok());
+
+        let short = compress::deflate(&filtered[1..], Vec::new()).unwrap();
+        let long = compress::deflate(&[filtered.as_slice(), &[0]].concat(), Vec::new()).unwrap();
+        let mut bad_filter = filtered.clone();
+        bad_filter[0] = 5;
+        let bad_filter = compress::deflate(&bad_filter, Vec::new()).unwrap();
+        for data in [short, long, bad_filter, b"not zlib".to_vec()] {
+            assert!(matches!(
+                ImageData::from_zlib(ihdr.clone(), &data),
+                Err(PngError::InvalidImageData { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_image_too_large() {
+        let ihdr = Ihdr {
+            width: i32::MAX as u32,
+            height: i32::MAX as u32,
+            bit_depth: 16,
+            color_type: ColorType::Rgba,
+            compression_method: 0,
+            filter_method: 0,
+            interlace_method: InterlaceMethod::None,
+        };
+        let bomb = compress::deflate(&vec![0; 1 << 20], Vec::new()).unwrap();
+        for ihdr in [
+            ihdr.clone(),
+            Ihdr {
+                width: 100_000,
+                height: 100_000,
+                bit_depth: 8,
+                interlace_method: InterlaceMethod::Adam7,
+                ..ihdr
+            },
+        ] {
+            assert!(matches!(
+                ImageData::from_zlib(ihdr.clone(), &bomb),
+                Err(PngError::InvalidImageData { .. })
+            ));
+            assert!(matches!(
+                ImageData::new(ihdr, vec![]),
+                Err(PngError::InvalidImageData { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_new_checks_ihdr() {
+        let ihdr = Ihdr {
+            width: 2,
+            height: 2,
+            bit_depth: 3,
+            color_type: ColorType::Grayscale,
+            compression_method: 0,
+            filter_method: 0,
+            interlace_method: InterlaceMethod::None,
+        };
+        for ihdr in [
+            ihdr.clone(),
+            Ihdr {
+                bit_depth: 16,
+                color_type: ColorType::Indexed,
+                ..ihdr.clone()
+            },
+            Ihdr {
+                width: 0,
+                bit_depth: 8,
+                ..ihdr
+            },
+        ] {
+            let samples = ihdr.width as usize * ihdr.height as usize * 2;
+            assert!(matches!(
+                ImageData::new(ihdr, vec![0; samples]),
+                Err(PngError::InvalidIhdr { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,36 @@
+pub mod analyze;
+pub mod apng;
+pub mod chunk;
+pub mod chunk_reader;
+pub mod chunk_type;
+pub mod commands;
+pub mod compress;
+pub mod crypto;
+pub mod diff;
+pub mod error;
+pub mod ihdr;
+pub mod image;
+pub mod output;
+pub mod payload;
+pub mod png;
+pub mod png_writer;
+pub mod repair;
+pub mod signature;
+pub mod stego;
+pub mod text;
+pub mod trailer;
+pub mod validate;
+
+pub use chunk::Chunk;
+pub use chunk_reader::ChunkReader;
+pub use chunk_type::ChunkType;
+pub use error::PngError;
+pub use ihdr::{ColorType, Ihdr, InterlaceMethod};
+pub use image::{EncodingOptions, FilterStrategy, ImageData};
+pub use png::{ChunkPosition, CrcError, Png};
+pub use png_writer::PngWriter;
+pub use text::TextChunk;
+pub use validate::Violation;
+
+pub type Error = PngError;
+pub type Result<T> = std::result::Result<T, Error>;
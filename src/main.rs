@@ -1,38 +1,262 @@
+use std::{env, io, path::PathBuf, process::ExitCode};
+
 use clap::Parser;
 
-use crate::{
-    args::{Args, Commands},
-    commands::{decode, encode, print, remove},
+use pngme::{
+    commands::{
+        analyze, apng_extract_frame, apng_frames, apng_set_loops, check, decode, diff, encode,
+        info, keygen, list, print, remove, repair, stego_capacity, stego_embed, stego_extract,
+        text_get, text_list, text_remove, text_set, trailer_extract, trailer_show, trailer_strip,
+        verify, DecodeOptions, EncodeInput, EncodeOptions, TextOptions,
+    },
+    PngError, Result,
+};
+
+use crate::args::{
+    ApngCommands, Args, Commands, Format, StegoCommands, TextCommands, TrailerCommands,
 };
 
 mod args;
-mod chunk;
-mod chunk_type;
-mod commands;
-mod png;
 
-pub type Error = Box<dyn std::error::Error>;
-pub type Result<T> = std::result::Result<T, Error>;
+const PASSPHRASE_VAR: &str = "PNGME_PASSPHRASE";
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+// Takes the passphrase from the environment when set so pngme can be
+// scripted, otherwise asks on the terminal.
+fn read_passphrase(confirm: bool) -> Result<String> {
+    if let Ok(passphrase) = env::var(PASSPHRASE_VAR) {
+        return Ok(passphrase);
+    }
+    let passphrase = rpassword::prompt_password("Passphrase: ")?;
+    if confirm && rpassword::prompt_password("Confirm passphrase: ")? != passphrase {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Passphrases do not match").into());
+    }
+    Ok(passphrase)
+}
+
+// Picks the payload source; clap requires exactly one of the message, --file
+// and --stdin.
+fn encode_input(message: Option<String>, file: Option<PathBuf>) -> EncodeInput {
+    match (message, file) {
+        (Some(message), _) => EncodeInput::Message(message),
+        (None, Some(path)) => EncodeInput::File(path),
+        (None, None) => EncodeInput::Stdin,
+    }
+}
+
+// Returns what to print and the exit code; only diff exits with anything but
+// success without an error, using 1 when the files differ like diff(1).
+fn run(args: Args) -> Result<(String, ExitCode)> {
+    if args.format != Format::Text && !args.command.supports_format() {
+        return Err(PngError::Unsupported {
+            reason: "--format with this command".into(),
+        });
+    }
+    let format = args.format.into();
     let message = match args.command {
         Commands::Encode {
             file_path,
             chunk_type,
             message,
             output_file,
-        } => encode(file_path, chunk_type, message, output_file)?,
+            output,
+            file,
+            stdin: _,
+            position,
+            encrypt,
+            recipient,
+            sign,
+            sign_critical,
+            max_chunk_size,
+            compress,
+        } => {
+            let options = EncodeOptions {
+                position: position.into(),
+                passphrase: if encrypt {
+                    Some(read_passphrase(true)?)
+                } else {
+                    None
+                },
+                recipient,
+                sign,
+                sign_critical,
+                max_chunk_size,
+                compression: compress.map(Into::into),
+            };
+            let input = encode_input(message, file);
+            encode(
+                file_path,
+                chunk_type,
+                input,
+                output_file.or(output),
+                options,
+            )
+        }
         Commands::Decode {
             file_path,
             chunk_type,
-        } => decode(file_path, chunk_type)?,
+            decrypt,
+            identity,
+            output,
+            raw,
+            max_output_size,
+        } => {
+            let options = DecodeOptions {
+                passphrase: if decrypt {
+                    Some(read_passphrase(false)?)
+                } else {
+                    None
+                },
+                identity,
+                output,
+                raw,
+                max_output_size,
+                format,
+            };
+            decode(file_path, chunk_type, options)
+        }
         Commands::Remove {
             file_path,
             chunk_type,
-        } => remove(file_path, chunk_type)?,
-        Commands::Print { file_path } => print(file_path)?,
-    };
-    println!("{}", message);
-    Ok(())
+        } => remove(file_path, chunk_type),
+        Commands::Print { file_path } => print(file_path, format),
+        Commands::List {
+            file_path,
+            hexdump,
+            max_bytes,
+        } => list(file_path, hexdump, max_bytes, format),
+        Commands::Check { file_path } => check(file_path),
+        Commands::Info { file_path } => info(file_path, format),
+        Commands::Keygen {
+            output_prefix,
+            kind,
+        } => keygen(output_prefix, kind.into()),
+        Commands::Verify { file_path, key } => verify(file_path, key),
+        Commands::Text { command } => match command {
+            TextCommands::List { file_path } => text_list(file_path),
+            TextCommands::Get { file_path, keyword } => text_get(file_path, keyword),
+            TextCommands::Set {
+                file_path,
+                keyword,
+                text,
+                compress,
+                language,
+                translated_keyword,
+            } => {
+                let options = TextOptions {
+                    compress,
+                    language_tag: language,
+                    translated_keyword,
+                };
+                text_set(file_path, keyword, text, options)
+            }
+            TextCommands::Remove { file_path, keyword } => text_remove(file_path, keyword),
+        },
+        Commands::Diff { old_file, new_file } => {
+            let (report, identical) = diff(old_file, new_file, format)?;
+            let code = if identical {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::from(1)
+            };
+            return Ok((report, code));
+        }
+        Commands::Analyze { file_path } => analyze(file_path),
+        Commands::Stego { command } => match command {
+            StegoCommands::Embed {
+                file_path,
+                message,
+                output_file,
+                output,
+                file,
+                stdin: _,
+                encrypt,
+                recipient,
+                compress,
+                stego,
+            } => {
+                let options = EncodeOptions {
+                    passphrase: if encrypt {
+                        Some(read_passphrase(true)?)
+                    } else {
+                        None
+                    },
+                    recipient,
+                    compression: compress.map(Into::into),
+                    ..EncodeOptions::default()
+                };
+                let input = encode_input(message, file);
+                stego_embed(
+                    file_path,
+                    input,
+                    output_file.or(output),
+                    stego.into(),
+                    options,
+                )
+            }
+            StegoCommands::Extract {
+                file_path,
+                decrypt,
+                identity,
+                output,
+                raw,
+                max_output_size,
+                stego,
+            } => {
+                let options = DecodeOptions {
+                    passphrase: if decrypt {
+                        Some(read_passphrase(false)?)
+                    } else {
+                        None
+                    },
+                    identity,
+                    output,
+                    raw,
+                    max_output_size,
+                    format,
+                };
+                stego_extract(file_path, stego.into(), options)
+            }
+            StegoCommands::Capacity { file_path, stego } => stego_capacity(file_path, stego.into()),
+        },
+        Commands::Apng { command } => match command {
+            ApngCommands::Frames { file_path } => apng_frames(file_path),
+            ApngCommands::ExtractFrame {
+                file_path,
+                index,
+                output,
+            } => apng_extract_frame(file_path, index, output),
+            ApngCommands::SetLoops { file_path, loops } => apng_set_loops(file_path, loops),
+        },
+        Commands::Trailer { command } => match command {
+            TrailerCommands::Show { file_path } => trailer_show(file_path),
+            TrailerCommands::Extract { file_path, output } => trailer_extract(file_path, output),
+            TrailerCommands::Strip {
+                file_path,
+                output_file,
+            } => trailer_strip(file_path, output_file),
+        },
+        Commands::Repair {
+            file_path,
+            output_file,
+            correct_bits,
+        } => repair(file_path, output_file, correct_bits, format),
+    }?;
+    Ok((message, ExitCode::SUCCESS))
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(args) {
+        Ok((message, code)) => {
+            // Raw output has already been written by the command itself.
+            if !message.is_empty() {
+                println!("{}", message);
+            }
+            code
+        }
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            ExitCode::from(error.exit_code())
+        }
+    }
 }
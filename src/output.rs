@@ -0,0 +1,184 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Serialize;
+
+use crate::{chunk::Chunk, chunk_type::ChunkType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Yaml,
+}
+
+/// The formats [`render`] produces; text output is left to each command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StructuredFormat {
+    Json,
+    Yaml,
+}
+
+impl OutputFormat {
+    /// The structured format to render, or `None` for text.
+    pub fn structured(self) -> Option<StructuredFormat> {
+        match self {
+            OutputFormat::Text => None,
+            OutputFormat::Json => Some(StructuredFormat::Json),
+            OutputFormat::Yaml => Some(StructuredFormat::Yaml),
+        }
+    }
+}
+
+/// Bytes as text when they are UTF-8, base64 otherwise.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "encoding", content = "data", rename_all = "lowercase")]
+pub enum Data {
+    Utf8(String),
+    Base64(String),
+}
+
+impl Data {
+    pub fn new(bytes: Vec<u8>) -> Data {
+        match String::from_utf8(bytes) {
+            Ok(text) => Data::Utf8(text),
+            Err(error) => Data::Base64(STANDARD.encode(error.into_bytes())),
+        }
+    }
+}
+
+/// Everything about one chunk, for scripts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChunkRecord {
+    pub index: usize,
+    /// Byte offset of the chunk's length field in the file.
+    pub offset: usize,
+    pub chunk_type: ChunkType,
+    pub length: u32,
+    pub crc: u32,
+    pub critical: bool,
+    pub public: bool,
+    pub reserved_bit_valid: bool,
+    pub safe_to_copy: bool,
+    #[serde(flatten)]
+    pub data: Data,
+}
+
+impl ChunkRecord {
+    pub fn new(index: usize, offset: usize, chunk: &Chunk) -> ChunkRecord {
+        let chunk_type = chunk.chunk_type();
+        ChunkRecord {
+            index,
+            offset,
+            chunk_type: chunk_type.clone(),
+            length: chunk.length(),
+            crc: chunk.crc(),
+            critical: chunk_type.is_critical(),
+            public: chunk_type.is_public(),
+            reserved_bit_valid: chunk_type.is_reserved_bit_valid(),
+            safe_to_copy: chunk_type.is_safe_to_copy(),
+            data: Data::new(chunk.data().to_vec()),
+        }
+    }
+}
+
+/// Renders `value` as JSON or YAML.
+pub fn render<T: Serialize>(value: &T, format: StructuredFormat) -> String {
+    // Every type rendered here has string keys and no maps that could fail
+    // to serialize.
+    match format {
+        StructuredFormat::Json => serde_json::to_string_pretty(value).expect("value serializes"),
+        StructuredFormat::Yaml => serde_yaml::to_string(value)
+            .expect("value serializes")
+            .trim_end()
+            .to_string(),
+    }
+}
+
+/// Formats at most `max_bytes` of `data` as hex and ASCII, 16 bytes a line,
+/// each line indented by two spaces.
+pub fn hexdump(data: &[u8], max_bytes: usize) -> String {
+    let mut lines: Vec<String> = data[..data.len().min(max_bytes)]
+        .chunks(16)
+        .enumerate()
+        .map(|(row, bytes)| {
+            let hex: Vec<String> = (0..16)
+                .map(|i| match bytes.get(i) {
+                    Some(byte) => format!("{:02x}", byte),
+                    None => "  ".into(),
+                })
+                .collect();
+            let ascii: String = bytes
+                .iter()
+                .map(|&byte| match byte {
+                    0x20..=0x7e => byte as char,
+                    _ => '.',
+                })
+                .collect();
+            format!(
+                "  {:08x}  {}  {}  |{}|",
+                row * 16,
+                hex[..8].join(" "),
+                hex[8..].join(" "),
+                ascii
+            )
+        })
+        .collect();
+    if data.len() > max_bytes {
+        lines.push(format!("  ... {} more bytes", data.len() - max_bytes));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_record() {
+        let chunk = Chunk::new("ruSt".parse().unwrap(), b"hello".to_vec());
+        let record = ChunkRecord::new(1, 33, &chunk);
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&record, StructuredFormat::Json)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "index": 1,
+                "offset": 33,
+                "chunk_type": "ruSt",
+                "length": 5,
+                "crc": chunk.crc(),
+                "critical": false,
+                "public": false,
+                "reserved_bit_valid": true,
+                "safe_to_copy": true,
+                "encoding": "utf8",
+                "data": "hello",
+            })
+        );
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let data: Vec<u8> = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\0\x01".to_vec();
+        assert_eq!(
+            hexdump(&data, 100),
+            "  00000000  89 50 4e 47 0d 0a 1a 0a  00 00 00 0d 49 48 44 52  |.PNG........IHDR|\n\
+             \x20 00000010  00 00 00 01                                       |....|"
+        );
+        assert_eq!(
+            hexdump(&data, 4),
+            "  00000000  89 50 4e 47                                       |.PNG|\n  ... 16 more bytes"
+        );
+        assert_eq!(hexdump(&[], 16), "");
+    }
+
+    #[test]
+    fn test_binary_data() {
+        assert_eq!(
+            Data::new(vec![0xff, 0x00, 0x10]),
+            Data::Base64("/wAQ".into())
+        );
+        let yaml = render(&Data::new(vec![0xff]), StructuredFormat::Yaml);
+        assert_eq!(yaml, "encoding: base64\ndata: /w==");
+    }
+}
@@ -0,0 +1,366 @@
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+
+use crate::{compress, crypto, error::PngError, Result};
+
+// Embedded files are wrapped in a small header so they can be restored with
+// their original name and checked for truncation:
+//
+//   magic (4) | version (1) | name length (2) | name | size (8) | contents
+//
+// Plain text messages are stored as they are, without this header.
+const MAGIC: &[u8; 4] = b"PMFL";
+const VERSION: u8 = 1;
+
+// Unless they start with one of the magic numbers payloads are recognized
+// by, in which case they get this prefix so decode takes them literally.
+const ESCAPE_MAGIC: &[u8; 4] = b"PMRW";
+
+// Payloads too large for one chunk are split into parts that each start with:
+//
+//   magic (4) | version (1) | payload id (8) | sequence (4) | total (4)
+//
+// The payload id ties the parts of one payload together so parts left over
+// from an earlier encode are not mixed in.
+const PART_MAGIC: &[u8; 4] = b"PMSQ";
+const PART_VERSION: u8 = 1;
+pub const PART_HEADER_LEN: usize = PART_MAGIC.len() + 1 + 8 + 4 + 4;
+
+/// Raw bytes embedded from a file or from standard input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePayload {
+    /// The original file name, empty when the bytes came from standard input.
+    pub name: String,
+    pub contents: Vec<u8>,
+}
+
+fn invalid(reason: &str) -> PngError {
+    PngError::InvalidPayload {
+        reason: reason.into(),
+    }
+}
+
+/// Whether `data` looks like a payload produced by [`FilePayload::to_bytes`].
+pub fn is_file_payload(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+fn is_reserved(message: &[u8]) -> bool {
+    [
+        MAGIC,
+        PART_MAGIC,
+        ESCAPE_MAGIC,
+        compress::MAGIC,
+        crypto::MAGIC,
+    ]
+    .iter()
+    .any(|magic| message.starts_with(*magic))
+}
+
+/// Prepares a plain message for storing, so it cannot be mistaken for any
+/// other kind of payload.
+pub fn escape_message(message: Vec<u8>) -> Vec<u8> {
+    match is_reserved(&message) {
+        true => [ESCAPE_MAGIC.as_slice(), &message].concat(),
+        false => message,
+    }
+}
+
+/// Reverses [`escape_message`].
+pub fn unescape_message(data: Vec<u8>) -> Vec<u8> {
+    match data.starts_with(ESCAPE_MAGIC) {
+        true => data[ESCAPE_MAGIC.len()..].to_vec(),
+        false => data,
+    }
+}
+
+impl FilePayload {
+    pub fn new(name: String, contents: Vec<u8>) -> FilePayload {
+        FilePayload { name, contents }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // Names longer than the length field allows are cut on a char boundary.
+        let mut name_len = self.name.len().min(u16::MAX as usize);
+        while !self.name.is_char_boundary(name_len) {
+            name_len -= 1;
+        }
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&(name_len as u16).to_be_bytes());
+        bytes.extend_from_slice(&self.name.as_bytes()[..name_len]);
+        bytes.extend_from_slice(&(self.contents.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(&self.contents);
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for FilePayload {
+    type Error = crate::Error;
+    fn try_from(value: &[u8]) -> Result<Self> {
+        if !is_file_payload(value) || value.len() < MAGIC.len() + 3 {
+            return Err(invalid("data is not an embedded file"));
+        }
+        if value[MAGIC.len()] != VERSION {
+            return Err(PngError::InvalidPayload {
+                reason: format!("unsupported file payload version {}", value[MAGIC.len()]),
+            });
+        }
+        let rest = &value[MAGIC.len() + 1..];
+        let name_len = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+        let rest = &rest[2..];
+        if rest.len() < name_len + 8 {
+            return Err(invalid("embedded file header is truncated"));
+        }
+        let (name, rest) = rest.split_at(name_len);
+        let name = String::from_utf8(name.to_vec())
+            .map_err(|_| invalid("embedded file name is not valid UTF-8"))?;
+        let (size, contents) = rest.split_at(8);
+        let size = u64::from_be_bytes(size.try_into().unwrap());
+        if size != contents.len() as u64 {
+            return Err(PngError::InvalidPayload {
+                reason: format!(
+                    "embedded file should be {} bytes but found {}",
+                    size,
+                    contents.len()
+                ),
+            });
+        }
+        Ok(FilePayload {
+            name,
+            contents: contents.to_vec(),
+        })
+    }
+}
+
+/// Whether `data` is one part of a payload produced by [`split`].
+pub fn is_part(data: &[u8]) -> bool {
+    data.starts_with(PART_MAGIC)
+}
+
+/// Splits `data` into parts of at most `max_chunk_size` bytes, headers
+/// included, that [`join`] can put back together.
+pub fn split(data: &[u8], max_chunk_size: usize) -> Result<Vec<Vec<u8>>> {
+    if max_chunk_size <= PART_HEADER_LEN {
+        return Err(PngError::InvalidPayload {
+            reason: format!(
+                "chunks must be larger than the {} byte part header",
+                PART_HEADER_LEN
+            ),
+        });
+    }
+    let pieces: Vec<&[u8]> = match data.is_empty() {
+        true => vec![data],
+        false => data.chunks(max_chunk_size - PART_HEADER_LEN).collect(),
+    };
+    let total = u32::try_from(pieces.len()).map_err(|_| invalid("payload has too many parts"))?;
+    let id = OsRng.next_u64();
+    Ok(pieces
+        .iter()
+        .enumerate()
+        .map(|(sequence, piece)| {
+            let mut part = PART_MAGIC.to_vec();
+            part.push(PART_VERSION);
+            part.extend_from_slice(&id.to_be_bytes());
+            part.extend_from_slice(&(sequence as u32).to_be_bytes());
+            part.extend_from_slice(&total.to_be_bytes());
+            part.extend_from_slice(piece);
+            part
+        })
+        .collect())
+}
+
+struct Part<'a> {
+    id: u64,
+    sequence: u32,
+    total: u32,
+    data: &'a [u8],
+}
+
+fn parse_part(data: &[u8]) -> Result<Part<'_>> {
+    if !is_part(data) || data.len() < PART_HEADER_LEN {
+        return Err(invalid("data is not a payload part"));
+    }
+    if data[PART_MAGIC.len()] != PART_VERSION {
+        return Err(PngError::InvalidPayload {
+            reason: format!(
+                "unsupported payload part version {}",
+                data[PART_MAGIC.len()]
+            ),
+        });
+    }
+    let field = |start: usize, len: usize| &data[PART_MAGIC.len() + 1 + start..][..len];
+    Ok(Part {
+        id: u64::from_be_bytes(field(0, 8).try_into().unwrap()),
+        sequence: u32::from_be_bytes(field(8, 4).try_into().unwrap()),
+        total: u32::from_be_bytes(field(12, 4).try_into().unwrap()),
+        data: &data[PART_HEADER_LEN..],
+    })
+}
+
+/// The payload id and number of parts declared by one part from [`split`].
+pub fn part_header(data: &[u8]) -> Result<(u64, u32)> {
+    let part = parse_part(data)?;
+    Ok((part.id, part.total))
+}
+
+/// Reassembles a payload from parts produced by [`split`], in any order.
+/// Only parts sharing the payload id of the first one are used; a missing or
+/// repeated part is an error.
+pub fn join<'a, I>(parts: I) -> Result<Vec<u8>>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    let mut parts = parts
+        .into_iter()
+        .map(parse_part)
+        .collect::<Result<Vec<_>>>()?;
+    let (id, total) = match parts.first() {
+        Some(first) => (first.id, first.total),
+        None => return Err(invalid("payload has no parts")),
+    };
+    parts.retain(|part| part.id == id);
+    parts.sort_by_key(|part| part.sequence);
+
+    let mut data = Vec::new();
+    for (expected, part) in (0..total).zip(parts.iter()) {
+        if part.total != total {
+            return Err(invalid("payload parts disagree on the number of parts"));
+        }
+        if part.sequence != expected {
+            let reason = match part.sequence < expected {
+                true => format!(
+                    "part {} of the payload appears more than once",
+                    part.sequence + 1
+                ),
+                false => format!(
+                    "part {} of {} of the payload is missing",
+                    expected + 1,
+                    total
+                ),
+            };
+            return Err(PngError::InvalidPayload { reason });
+        }
+        data.extend_from_slice(part.data);
+    }
+    match parts.len() as u64 {
+        found if found < total as u64 => Err(PngError::InvalidPayload {
+            reason: format!("part {} of {} of the payload is missing", found + 1, total),
+        }),
+        found if found > total as u64 => Err(invalid("payload has more parts than it declares")),
+        _ => Ok(data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let payload = FilePayload::new("dice.png".into(), vec![0, 159, 146, 150, 255]);
+        let bytes = payload.to_bytes();
+        assert!(is_file_payload(&bytes));
+        assert_eq!(FilePayload::try_from(bytes.as_slice()).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_unnamed_payload() {
+        let payload = FilePayload::new(String::new(), b"from stdin".to_vec());
+        let bytes = payload.to_bytes();
+        assert_eq!(FilePayload::try_from(bytes.as_slice()).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_truncated_payload() {
+        let bytes = FilePayload::new("notes.txt".into(), b"contents".to_vec()).to_bytes();
+        for len in [0, 6, 12, bytes.len() - 1] {
+            assert!(matches!(
+                FilePayload::try_from(&bytes[..len]),
+                Err(PngError::InvalidPayload { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_plain_message() {
+        assert!(!is_file_payload(b"This is a secret message"));
+        assert!(FilePayload::try_from(b"This is a secret message".as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_escape_message() {
+        for message in ["PMCZ is here", "PMSL", "PMFL file", "PMSQ part", "PMRW"] {
+            let escaped = escape_message(message.into());
+            assert!(escaped.starts_with(ESCAPE_MAGIC), "{}", message);
+            assert!(!is_file_payload(&escaped) && !is_part(&escaped));
+            assert_eq!(unescape_message(escaped), message.as_bytes());
+        }
+        assert_eq!(escape_message(b"PM only".to_vec()), b"PM only");
+        assert_eq!(unescape_message(b"plain".to_vec()), b"plain");
+    }
+
+    fn part_slices(parts: &[Vec<u8>]) -> Vec<&[u8]> {
+        parts.iter().map(|part| part.as_slice()).collect()
+    }
+
+    #[test]
+    fn test_split_and_join() {
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let parts = split(&data, 100).unwrap();
+        assert_eq!(parts.len(), 13);
+        assert!(parts.iter().all(|part| part.len() <= 100 && is_part(part)));
+        assert_eq!(join(part_slices(&parts)).unwrap(), data);
+        let (id, total) = part_header(&parts[0]).unwrap();
+        assert_eq!(total, 13);
+        assert!(parts.iter().all(|part| part_header(part).unwrap().0 == id));
+
+        let mut shuffled = part_slices(&parts);
+        shuffled.reverse();
+        assert_eq!(join(shuffled).unwrap(), data);
+    }
+
+    #[test]
+    fn test_empty_payload() {
+        let parts = split(&[], 100).unwrap();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(join(part_slices(&parts)).unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_chunk_size_too_small() {
+        assert!(split(b"data", PART_HEADER_LEN).is_err());
+        assert_eq!(split(b"data", PART_HEADER_LEN + 1).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_missing_part() {
+        let parts = split(&[7; 100], 40).unwrap();
+        for missing in 0..parts.len() {
+            let mut slices = part_slices(&parts);
+            slices.remove(missing);
+            let error = join(slices).unwrap_err();
+            assert!(
+                matches!(error, PngError::InvalidPayload { .. }),
+                "{}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn test_duplicate_part() {
+        let parts = split(&[7; 100], 40).unwrap();
+        let mut slices = part_slices(&parts);
+        slices.push(slices[1]);
+        assert!(join(slices).is_err());
+    }
+
+    #[test]
+    fn test_parts_of_other_payload_ignored() {
+        let parts = split(b"first payload", 24).unwrap();
+        let other = split(b"second payload", 24).unwrap();
+        let mut slices = part_slices(&parts);
+        slices.extend(part_slices(&other));
+        assert_eq!(join(slices).unwrap(), b"first payload");
+    }
+}
@@ -1,29 +1,135 @@
 use std::{fmt::Display, str::FromStr};
 
-use crate::{chunk::Chunk, chunk_type::ChunkType};
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    error::PngError,
+    ihdr::Ihdr,
+    validate::{self, Violation},
+};
+
+/// Where a new chunk goes relative to the image data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkPosition {
+    BeforeIdat,
+    AfterIdat,
+    BeforeIend,
+}
+
+impl ChunkPosition {
+    /// Whether a chunk placed in this position belongs between `previous`
+    /// and `next`. Only the first matching gap in a file should be used.
+    pub fn is_insertion_point(&self, previous: Option<&ChunkType>, next: &ChunkType) -> bool {
+        match self {
+            // In an animated PNG the IDAT image may be the first frame, whose
+            // fcTL has to stay right before the IDAT chunks.
+            ChunkPosition::BeforeIdat => next == &ChunkType::IDAT || next == &ChunkType::FCTL,
+            ChunkPosition::AfterIdat => {
+                previous == Some(&ChunkType::IDAT) && next != &ChunkType::IDAT
+            }
+            ChunkPosition::BeforeIend => next == &ChunkType::IEND,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Png {
     header: [u8; 8],
     chunks: Vec<Chunk>,
+    trailer: Vec<u8>,
+}
+
+/// A chunk whose stored CRC does not match its contents, found by
+/// [`Png::parse_lenient`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrcError {
+    pub index: usize,
+    /// Byte offset of the chunk's length field in the file.
+    pub offset: usize,
+    pub chunk_type: ChunkType,
+    pub stored: u32,
+    pub calculated: u32,
 }
 
 impl TryFrom<&[u8]> for Png {
     type Error = crate::Error;
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        Png::parse(value, false).map(|(png, _)| png)
+    }
+}
+
+impl Png {
+    // With `lenient` set, chunks with a bad CRC are kept, CRC and all, and
+    // reported rather than failing the parse.
+    fn parse(value: &[u8], lenient: bool) -> crate::Result<(Png, Vec<CrcError>)> {
+        if value.len() < 8 {
+            return Err(PngError::Truncated {
+                offset: 0,
+                needed: 8,
+                available: value.len(),
+            });
+        }
         let header: [u8; 8] = value[0..8].try_into().unwrap();
         if header != Png::STANDARD_HEADER {
-            return Err("PNG header is invalid".into());
+            return Err(PngError::InvalidSignature {
+                found: header.to_vec(),
+            });
         }
         let mut chunks: Vec<Chunk> = vec![];
+        let mut crc_errors = Vec::new();
         let mut index: usize = 8;
         while index < value.len() {
+            let remaining = value.len() - index;
+            if remaining < 12 {
+                return Err(PngError::Truncated {
+                    offset: index,
+                    needed: 12,
+                    available: remaining,
+                });
+            }
             let length = u32::from_be_bytes(value[index..index + 4].try_into().unwrap());
+            if length as usize > remaining - 12 {
+                return Err(PngError::ChunkLengthMismatch {
+                    offset: index,
+                    declared: length,
+                    available: remaining - 12,
+                });
+            }
             let end_index = index + length as usize + 12;
-            chunks.push(TryFrom::try_from(&value[index..end_index])?);
-            index = end_index
+            let chunk = match Chunk::try_from(&value[index..end_index]) {
+                Err(PngError::CrcMismatch {
+                    chunk_type,
+                    expected,
+                    actual,
+                    ..
+                }) if lenient => {
+                    crc_errors.push(CrcError {
+                        index: chunks.len(),
+                        offset: index,
+                        chunk_type: chunk_type.clone(),
+                        stored: actual,
+                        calculated: expected,
+                    });
+                    let data = value[index + 8..end_index - 4].to_vec();
+                    Chunk::with_unchecked_crc(chunk_type, data, actual)
+                }
+                result => result.map_err(|error| error.shift_offset(index))?,
+            };
+            let is_iend = chunk.chunk_type() == &ChunkType::IEND;
+            chunks.push(chunk);
+            index = end_index;
+            // Anything after IEND is not part of the image and need not be
+            // made of chunks, so it is kept as is.
+            if is_iend {
+                break;
+            }
         }
-        Ok(Png { header, chunks })
+        let png = Png {
+            header,
+            chunks,
+            trailer: value[index..].to_vec(),
+        };
+        Ok((png, crc_errors))
     }
 }
 
@@ -36,15 +142,76 @@ impl Display for Png {
 impl Png {
     pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
-    fn from_chunks(chunks: Vec<Chunk>) -> Png {
+    /// Parses `value` like [`Png::try_from`], but keeps chunks whose CRC is
+    /// wrong and reports them instead of failing.
+    pub fn parse_lenient(value: &[u8]) -> crate::Result<(Png, Vec<CrcError>)> {
+        Png::parse(value, true)
+    }
+
+    pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
         Png {
             header: Png::STANDARD_HEADER,
             chunks,
+            trailer: Vec::new(),
         }
     }
 
+    /// Adds a chunk before IEND, or at the very end if there is no IEND.
     pub fn append_chunk(&mut self, chunk: Chunk) {
-        self.chunks.push(chunk);
+        self.insert_chunk(chunk, ChunkPosition::BeforeIend);
+    }
+
+    /// Adds a chunk at `position`. If the file has no such place (for example
+    /// no IDAT chunks) it goes before IEND instead, or at the very end if
+    /// there is no IEND either.
+    pub fn insert_chunk(&mut self, chunk: Chunk, position: ChunkPosition) {
+        let index = (0..self.chunks.len()).find(|&i| {
+            let previous = i.checked_sub(1).map(|i| self.chunks[i].chunk_type());
+            position.is_insertion_point(previous, self.chunks[i].chunk_type())
+                || self.chunks[i].chunk_type() == &ChunkType::IEND
+        });
+        match index {
+            Some(index) => self.chunks.insert(index, chunk),
+            None => self.chunks.push(chunk),
+        }
+    }
+
+    pub fn insert_chunk_at(&mut self, index: usize, chunk: Chunk) -> crate::Result<()> {
+        if index > self.chunks.len() {
+            return Err(PngError::IndexOutOfBounds {
+                index,
+                len: self.chunks.len(),
+            });
+        }
+        self.chunks.insert(index, chunk);
+        Ok(())
+    }
+
+    /// Inserts a chunk right before the first chunk of `chunk_type`.
+    pub fn insert_before(&mut self, chunk_type: &str, chunk: Chunk) -> crate::Result<()> {
+        let chunk_type = ChunkType::from_str(chunk_type)?;
+        match self
+            .chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type() == &chunk_type)
+        {
+            Some(index) => self.insert_chunk_at(index, chunk),
+            None => Err(PngError::ChunkNotFound { chunk_type }),
+        }
+    }
+
+    /// Inserts a chunk right after the last chunk of `chunk_type`, so that
+    /// runs such as consecutive IDAT chunks are never split.
+    pub fn insert_after(&mut self, chunk_type: &str, chunk: Chunk) -> crate::Result<()> {
+        let chunk_type = ChunkType::from_str(chunk_type)?;
+        match self
+            .chunks
+            .iter()
+            .rposition(|chunk| chunk.chunk_type() == &chunk_type)
+        {
+            Some(index) => self.insert_chunk_at(index + 1, chunk),
+            None => Err(PngError::ChunkNotFound { chunk_type }),
+        }
     }
 
     pub fn remove_chunk(&mut self, chunk_type: &str) -> crate::Result<Chunk> {
@@ -56,12 +223,30 @@ impl Png {
             .find(|(_, chunk)| chunk.chunk_type() == &chunk_type)
         {
             Some((i, _)) => i,
-            None => return Err("Chunk type not found".into()),
+            None => return Err(PngError::ChunkNotFound { chunk_type }),
         };
         Ok(self.chunks.remove(index))
     }
 
-    fn header(&self) -> &[u8; 8] {
+    /// Replaces every IDAT chunk with `idat_chunks`, placed where the first
+    /// IDAT chunk was, or before IEND if there was none.
+    pub fn replace_image_data(&mut self, idat_chunks: Vec<Chunk>) {
+        let index = self
+            .chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type() == &ChunkType::IDAT);
+        self.chunks
+            .retain(|chunk| chunk.chunk_type() != &ChunkType::IDAT);
+        let index = index.unwrap_or_else(|| {
+            self.chunks
+                .iter()
+                .position(|chunk| chunk.chunk_type() == &ChunkType::IEND)
+                .unwrap_or(self.chunks.len())
+        });
+        self.chunks.splice(index..index, idat_chunks);
+    }
+
+    pub fn header(&self) -> &[u8; 8] {
         &self.header
     }
 
@@ -69,6 +254,15 @@ impl Png {
         &self.chunks
     }
 
+    /// Bytes that followed IEND in the parsed file.
+    pub fn trailer(&self) -> &[u8] {
+        &self.trailer
+    }
+
+    pub fn set_trailer(&mut self, trailer: Vec<u8>) {
+        self.trailer = trailer;
+    }
+
     pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
         let chunk_type = match ChunkType::from_str(chunk_type) {
             Ok(chunk_type) => chunk_type,
@@ -79,11 +273,34 @@ impl Png {
             .find(|chunk| chunk.chunk_type() == &chunk_type)
     }
 
+    /// Parses the IHDR chunk into typed image metadata.
+    pub fn header_info(&self) -> crate::Result<Ihdr> {
+        match self
+            .chunks
+            .iter()
+            .find(|chunk| chunk.chunk_type() == &ChunkType::IHDR)
+        {
+            Some(chunk) => Ihdr::try_from(chunk),
+            None => Err(PngError::ChunkNotFound {
+                chunk_type: ChunkType::IHDR,
+            }),
+        }
+    }
+
+    /// Checks chunk ordering and multiplicity against the PNG specification,
+    /// and that nothing follows IEND.
+    pub fn validate(&self) -> Vec<Violation> {
+        let mut violations = validate::validate(&self.chunks);
+        violations.extend(validate::validate_trailer(self.chunks.len(), &self.trailer));
+        violations
+    }
+
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut bytes: Vec<u8> = self.header().to_vec();
         for chunk in self.chunks() {
             bytes.append(&mut chunk.as_bytes().to_vec());
         }
+        bytes.extend_from_slice(&self.trailer);
         bytes
     }
 }
@@ -206,6 +423,151 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "Message");
     }
 
+    fn testing_image_png() -> Png {
+        Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("IDAT", "first").unwrap(),
+            chunk_from_strings("IDAT", "second").unwrap(),
+            chunk_from_strings("tEXt", "text").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ])
+    }
+
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_append_chunk_before_iend() {
+        let mut png = testing_image_png();
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        assert_eq!(
+            chunk_types(&png),
+            vec!["IHDR", "IDAT", "IDAT", "tEXt", "TeSt", "IEND"]
+        );
+    }
+
+    #[test]
+    fn test_insert_chunk_positions() {
+        let mut png = testing_image_png();
+        png.insert_chunk(
+            chunk_from_strings("BeFr", "").unwrap(),
+            ChunkPosition::BeforeIdat,
+        );
+        png.insert_chunk(
+            chunk_from_strings("AfTr", "").unwrap(),
+            ChunkPosition::AfterIdat,
+        );
+        assert_eq!(
+            chunk_types(&png),
+            vec!["IHDR", "BeFr", "IDAT", "IDAT", "AfTr", "tEXt", "IEND"]
+        );
+    }
+
+    #[test]
+    fn test_replace_image_data() {
+        let mut png = testing_image_png();
+        png.replace_image_data(vec![chunk_from_strings("IDAT", "pixels").unwrap()]);
+        assert_eq!(chunk_types(&png), vec!["IHDR", "IDAT", "tEXt", "IEND"]);
+        assert_eq!(png.chunks()[1].data(), b"pixels");
+
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+        png.replace_image_data(vec![chunk_from_strings("IDAT", "pixels").unwrap()]);
+        assert_eq!(chunk_types(&png), vec!["IHDR", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_trailer() {
+        let mut bytes = testing_image_png().as_bytes();
+        let image_len = bytes.len();
+        // Looks like a chunk but comes after IEND, then garbage.
+        bytes.extend(chunk_from_strings("TeSt", "after").unwrap().as_bytes());
+        bytes.extend_from_slice(b"PK\x03\x04");
+
+        let png = Png::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(png.chunks().len(), 5);
+        assert_eq!(png.trailer(), &bytes[image_len..]);
+        assert_eq!(png.as_bytes(), bytes);
+        let violations = png.validate();
+        assert_eq!(
+            violations[violations.len() - 2..],
+            [
+                Violation::ChunkAfterIend {
+                    index: 5,
+                    chunk_type: ChunkType::from_str("TeSt").unwrap()
+                },
+                Violation::DataAfterIend { length: 4 },
+            ]
+        );
+
+        let mut png = png;
+        png.set_trailer(Vec::new());
+        assert_eq!(png.as_bytes(), &bytes[..image_len]);
+    }
+
+    #[test]
+    fn test_insert_chunk_without_anchor_goes_before_iend() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+        png.insert_chunk(
+            chunk_from_strings("TeSt", "").unwrap(),
+            ChunkPosition::AfterIdat,
+        );
+        assert_eq!(chunk_types(&png), vec!["IHDR", "TeSt", "IEND"]);
+    }
+
+    #[test]
+    fn test_insert_chunk_without_anchor_appends() {
+        let mut png = testing_png();
+        png.insert_chunk(
+            chunk_from_strings("TeSt", "").unwrap(),
+            ChunkPosition::AfterIdat,
+        );
+        assert_eq!(chunk_types(&png), vec!["FrSt", "miDl", "LASt", "TeSt"]);
+    }
+
+    #[test]
+    fn test_insert_chunk_at() {
+        let mut png = testing_png();
+        png.insert_chunk_at(1, chunk_from_strings("TeSt", "").unwrap())
+            .unwrap();
+        assert_eq!(chunk_types(&png), vec!["FrSt", "TeSt", "miDl", "LASt"]);
+
+        let error = png
+            .insert_chunk_at(9, chunk_from_strings("TeSt", "").unwrap())
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            PngError::IndexOutOfBounds { index: 9, len: 4 }
+        ));
+    }
+
+    #[test]
+    fn test_insert_before_and_after() {
+        let mut png = testing_image_png();
+        png.insert_before("IDAT", chunk_from_strings("BeFr", "").unwrap())
+            .unwrap();
+        png.insert_after("IDAT", chunk_from_strings("AfTr", "").unwrap())
+            .unwrap();
+        assert_eq!(
+            chunk_types(&png),
+            vec!["IHDR", "BeFr", "IDAT", "IDAT", "AfTr", "tEXt", "IEND"]
+        );
+
+        let error = png
+            .insert_after("PLTE", chunk_from_strings("TeSt", "").unwrap())
+            .unwrap_err();
+        assert!(matches!(error, PngError::ChunkNotFound { .. }));
+    }
+
     #[test]
     fn test_remove_chunk() {
         let mut png = testing_png();
@@ -215,6 +577,69 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_remove_missing_chunk() {
+        let mut png = testing_png();
+        let error = png.remove_chunk("TeSt").unwrap_err();
+        assert!(matches!(error, PngError::ChunkNotFound { .. }));
+    }
+
+    #[test]
+    fn test_crc_error_reports_file_offset() {
+        let mut bytes = Png::from_chunks(testing_chunks()).as_bytes();
+        // Corrupt the data of the second chunk.
+        let second_chunk_offset = 8 + 12 + "I am the first chunk".len();
+        bytes[second_chunk_offset + 8] ^= 0xff;
+
+        match Png::try_from(bytes.as_ref()) {
+            Err(PngError::CrcMismatch { offset, .. }) => assert_eq!(offset, second_chunk_offset),
+            other => panic!("expected CRC mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_truncated_file() {
+        // Cutting the file on a chunk boundary still leaves a parseable prefix,
+        // every other cut must be reported as an error.
+        for length in 0..PNG_FILE.len() {
+            if let Ok(png) = Png::try_from(&PNG_FILE[..length]) {
+                assert_eq!(png.as_bytes(), &PNG_FILE[..length]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_huge_declared_length() {
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        bytes.extend_from_slice(&u32::MAX.to_be_bytes());
+        bytes.extend_from_slice(b"RuSt");
+        bytes.extend_from_slice(&[0; 8]);
+
+        match Png::try_from(bytes.as_ref()) {
+            Err(PngError::ChunkLengthMismatch {
+                offset,
+                declared,
+                available,
+            }) => {
+                assert_eq!(offset, 8);
+                assert_eq!(declared, u32::MAX);
+                assert_eq!(available, 4);
+            }
+            other => panic!("expected length mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_header_info() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let ihdr = png.header_info().unwrap();
+        assert_eq!((ihdr.width, ihdr.height), (50, 50));
+        assert_eq!(ihdr.bit_depth, 8);
+        assert_eq!(ihdr.color_type, crate::ihdr::ColorType::Rgba);
+
+        assert!(testing_png().header_info().is_err());
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -0,0 +1,67 @@
+use std::io::Write;
+
+use crate::{chunk::Chunk, png::Png, Result};
+
+/// Writes a PNG stream chunk by chunk, starting with the signature.
+pub struct PngWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> PngWriter<W> {
+    pub fn new(mut writer: W) -> Result<PngWriter<W>> {
+        writer.write_all(&Png::STANDARD_HEADER)?;
+        Ok(PngWriter { writer })
+    }
+
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> Result<()> {
+        chunk.write_to(&mut self.writer)
+    }
+
+    /// Writes raw bytes after the last chunk, as found after IEND in some
+    /// files.
+    pub fn write_trailer(&mut self, trailer: &[u8]) -> Result<()> {
+        Ok(self.writer.write_all(trailer)?)
+    }
+
+    /// Flushes the stream and hands back the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn testing_chunks() -> Vec<Chunk> {
+        vec![
+            Chunk::new(
+                ChunkType::from_str("FrSt").unwrap(),
+                b"I am the first chunk".to_vec(),
+            ),
+            Chunk::new(
+                ChunkType::from_str("LASt").unwrap(),
+                b"I am the last chunk".to_vec(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_writes_signature() {
+        let bytes = PngWriter::new(Vec::new()).unwrap().finish().unwrap();
+        assert_eq!(bytes, Png::STANDARD_HEADER);
+    }
+
+    #[test]
+    fn test_matches_as_bytes() {
+        let mut writer = PngWriter::new(Vec::new()).unwrap();
+        for chunk in testing_chunks() {
+            writer.write_chunk(&chunk).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+        assert_eq!(bytes, Png::from_chunks(testing_chunks()).as_bytes());
+    }
+}
@@ -0,0 +1,285 @@
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    png::{CrcError, Png},
+};
+
+// Lookup table for the reflected CRC-32 polynomial PNG uses.
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = match c & 1 {
+                1 => 0xedb8_8320 ^ (c >> 1),
+                _ => c >> 1,
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+};
+
+/// What [`repair`] did to one chunk.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Fix {
+    /// The stored CRC was replaced by the one calculated from the chunk.
+    RecomputedCrc {
+        index: usize,
+        offset: usize,
+        chunk_type: ChunkType,
+        old_crc: u32,
+        new_crc: u32,
+    },
+    /// A single flipped bit was found and flipped back, so the stored CRC
+    /// matches again.
+    FlippedBit {
+        index: usize,
+        /// Byte offset of the flipped bit in the file.
+        offset: usize,
+        bit: u8,
+        chunk_type: ChunkType,
+    },
+}
+
+impl Display for Fix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Fix::RecomputedCrc {
+                index,
+                offset,
+                chunk_type,
+                old_crc,
+                new_crc,
+            } => write!(
+                f,
+                "Chunk {} at index {} (byte {}): CRC {:#010x} replaced with {:#010x}",
+                chunk_type, index, offset, old_crc, new_crc
+            ),
+            Fix::FlippedBit {
+                index,
+                offset,
+                bit,
+                chunk_type,
+            } => write!(
+                f,
+                "Chunk {} at index {}: flipped bit {} of byte {} back",
+                chunk_type, index, bit, offset
+            ),
+        }
+    }
+}
+
+// Where in a chunk's type and data a single flipped bit would explain the
+// difference `syndrome` between its stored and calculated CRCs, as
+// (byte index, bit). CRC-32 is linear, so flipping bit `b` of byte `i` out
+// of `len` changes the CRC by the raw CRC of that bit followed by
+// `len - 1 - i` zero bytes, whatever the rest of the message is. Walking
+// back from the last byte adds one zero byte per step, so every position
+// is checked in a single pass. At most two candidates are returned, which
+// is enough to tell whether the answer is unambiguous.
+fn single_bit_errors(len: usize, syndrome: u32) -> Vec<(usize, u8)> {
+    let mut registers: [u32; 8] = std::array::from_fn(|bit| CRC_TABLE[1 << bit]);
+    let mut candidates = Vec::new();
+    for index in (0..len).rev() {
+        for (bit, register) in registers.iter_mut().enumerate() {
+            if *register == syndrome {
+                candidates.push((index, bit as u8));
+                if candidates.len() == 2 {
+                    return candidates;
+                }
+            }
+            *register = (*register >> 8) ^ CRC_TABLE[(*register & 0xff) as usize];
+        }
+    }
+    candidates
+}
+
+// Tries to undo a single bit error in `chunk`, first in its type and data,
+// then in the stored CRC itself. Returns the fixed chunk and, if the error
+// was outside the CRC, where the flipped bit was.
+fn correct_bit(chunk: &Chunk) -> Option<(Chunk, Option<(usize, u8)>)> {
+    let syndrome = chunk.crc() ^ chunk.calculated_crc();
+    let mut message = chunk.chunk_type().bytes().to_vec();
+    message.extend_from_slice(chunk.data());
+    let mut candidates: Vec<Option<(usize, u8)>> = single_bit_errors(message.len(), syndrome)
+        .into_iter()
+        .map(Some)
+        .collect();
+    if syndrome.count_ones() == 1 {
+        candidates.push(None);
+    }
+    match candidates[..] {
+        [Some((index, bit))] => {
+            message[index] ^= 1 << bit;
+            let chunk_type =
+                ChunkType::try_from(<[u8; 4]>::try_from(&message[..4]).unwrap()).ok()?;
+            let fixed = Chunk::new(chunk_type, message[4..].to_vec());
+            Some((fixed, Some((index, bit))))
+        }
+        [None] => Some((
+            Chunk::new(chunk.chunk_type().clone(), chunk.data().to_vec()),
+            None,
+        )),
+        _ => None,
+    }
+}
+
+/// Fixes every chunk with a bad CRC in `png`, whose errors were found by
+/// [`Png::parse_lenient`]. With `correct_bits`, a chunk that differs from
+/// its CRC by one flipped bit gets that bit flipped back; otherwise, or if
+/// no single bit explains the error, the CRC is recalculated, which makes
+/// the file readable but keeps any damage to the data.
+pub fn repair(png: &Png, errors: &[CrcError], correct_bits: bool) -> (Png, Vec<Fix>) {
+    let mut chunks = png.chunks().to_vec();
+    let mut fixes = Vec::new();
+    for error in errors {
+        let chunk = &chunks[error.index];
+        let corrected = match correct_bits {
+            true => correct_bit(chunk),
+            false => None,
+        };
+        let fix = match corrected {
+            Some((fixed, Some((index, bit)))) => {
+                chunks[error.index] = fixed;
+                Fix::FlippedBit {
+                    index: error.index,
+                    // The message starts with the type, after the length.
+                    offset: error.offset + 4 + index,
+                    bit,
+                    chunk_type: chunks[error.index].chunk_type().clone(),
+                }
+            }
+            _ => {
+                chunks[error.index] = Chunk::new(chunk.chunk_type().clone(), chunk.data().to_vec());
+                Fix::RecomputedCrc {
+                    index: error.index,
+                    offset: error.offset,
+                    chunk_type: error.chunk_type.clone(),
+                    old_crc: error.stored,
+                    new_crc: error.calculated,
+                }
+            }
+        };
+        fixes.push(fix);
+    }
+    let mut repaired = Png::from_chunks(chunks);
+    repaired.set_trailer(png.trailer().to_vec());
+    (repaired, fixes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn damaged_png(flip_offset: usize, bit: u8) -> (Vec<u8>, Vec<u8>) {
+        let png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]),
+            Chunk::new(
+                "ruSt".parse().unwrap(),
+                b"This is a secret message".to_vec(),
+            ),
+            Chunk::new(ChunkType::IEND, vec![]),
+        ]);
+        let original = png.as_bytes();
+        let mut damaged = original.clone();
+        damaged[flip_offset] ^= 1 << bit;
+        (original, damaged)
+    }
+
+    // The ruSt chunk starts at byte 33: 8 for the signature, 25 for IHDR.
+    const RUST_OFFSET: usize = 33;
+
+    #[test]
+    fn test_lenient_parse() {
+        let (_, damaged) = damaged_png(RUST_OFFSET + 10, 2);
+        assert!(Png::try_from(damaged.as_slice()).is_err());
+        let (png, errors) = Png::parse_lenient(&damaged).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].index, 1);
+        assert_eq!(errors[0].offset, RUST_OFFSET);
+        assert_eq!(png.as_bytes(), damaged);
+    }
+
+    #[test]
+    fn test_recompute_crc() {
+        let (_, damaged) = damaged_png(RUST_OFFSET + 10, 2);
+        let (png, errors) = Png::parse_lenient(&damaged).unwrap();
+        let (repaired, fixes) = repair(&png, &errors, false);
+        assert!(matches!(fixes[..], [Fix::RecomputedCrc { index: 1, .. }]));
+        let bytes = repaired.as_bytes();
+        assert!(Png::try_from(bytes.as_slice()).is_ok());
+        assert_eq!(bytes[RUST_OFFSET + 10], damaged[RUST_OFFSET + 10]);
+    }
+
+    #[test]
+    fn test_correct_every_single_bit() {
+        // Every bit of the ruSt chunk's type, data and CRC.
+        let end = RUST_OFFSET + 12 + "This is a secret message".len();
+        for offset in RUST_OFFSET + 4..end {
+            for bit in 0..8 {
+                let (original, damaged) = damaged_png(offset, bit);
+                let Ok((png, errors)) = Png::parse_lenient(&damaged) else {
+                    // Some flips in the type make it invalid.
+                    assert!(offset < RUST_OFFSET + 8);
+                    continue;
+                };
+                let (repaired, fixes) = repair(&png, &errors, true);
+                assert_eq!(repaired.as_bytes(), original, "byte {} bit {}", offset, bit);
+                if offset < end - 4 {
+                    assert_eq!(
+                        fixes,
+                        vec![Fix::FlippedBit {
+                            index: 1,
+                            offset,
+                            bit,
+                            chunk_type: "ruSt".parse().unwrap(),
+                        }]
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_single_bit_errors_match_brute_force() {
+        let chunk_type: ChunkType = "ruSt".parse().unwrap();
+        let data = b"Brute force check".to_vec();
+        let crc = Chunk::new(chunk_type.clone(), data.clone()).crc();
+        let len = 4 + data.len();
+        for index in 0..len {
+            for bit in 0..8 {
+                let mut message = chunk_type.bytes().to_vec();
+                message.extend_from_slice(&data);
+                message[index] ^= 1 << bit;
+                let Ok(flipped_type) =
+                    ChunkType::try_from(<[u8; 4]>::try_from(&message[..4]).unwrap())
+                else {
+                    continue;
+                };
+                let damaged = Chunk::with_unchecked_crc(flipped_type, message[4..].to_vec(), crc);
+                let syndrome = damaged.crc() ^ damaged.calculated_crc();
+                assert_eq!(single_bit_errors(len, syndrome), vec![(index, bit)]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ambiguous_errors_are_recomputed() {
+        let (_, mut damaged) = damaged_png(RUST_OFFSET + 10, 2);
+        damaged[RUST_OFFSET + 20] ^= 0x11;
+        let (png, errors) = Png::parse_lenient(&damaged).unwrap();
+        let (repaired, fixes) = repair(&png, &errors, true);
+        assert!(matches!(fixes[..], [Fix::RecomputedCrc { .. }]));
+        assert!(Png::try_from(repaired.as_bytes().as_slice()).is_ok());
+    }
+}
@@ -0,0 +1,282 @@
+use std::{fmt::Display, str::FromStr};
+
+use chacha20poly1305::aead::OsRng;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use sha2::{Digest, Sha512};
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, crypto::KEY_LEN, error::PngError, Result};
+
+// Signature chunks hold:
+//
+//   version (1) | flags (1) | signed chunk type (4) | Ed25519 signature (64)
+//
+// The signature is over a SHA-512 digest that commits to every chunk of the
+// signed type and, with FLAG_CRITICAL, to every critical chunk except IEND.
+// Both sets are hashed separately so a file can be signed while streaming.
+const VERSION: u8 = 1;
+const FLAG_CRITICAL: u8 = 1;
+const DOMAIN: &[u8] = b"pngme signature v1\0";
+const DATA_LEN: usize = 2 + 4 + 64;
+
+pub fn signature_chunk_type() -> ChunkType {
+    ChunkType::from_str("siGn").unwrap()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureStatus {
+    Valid,
+    Invalid,
+    Missing,
+}
+
+impl Display for SignatureStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureStatus::Valid => write!(f, "valid"),
+            SignatureStatus::Invalid => write!(f, "invalid"),
+            SignatureStatus::Missing => write!(f, "missing"),
+        }
+    }
+}
+
+/// Accumulates the chunks a signature covers.
+pub struct SignatureHasher {
+    chunk_type: ChunkType,
+    covers_critical: bool,
+    signed: Sha512,
+    critical: Sha512,
+}
+
+impl SignatureHasher {
+    pub fn new(chunk_type: ChunkType, covers_critical: bool) -> SignatureHasher {
+        SignatureHasher {
+            chunk_type,
+            covers_critical,
+            signed: Sha512::new(),
+            critical: Sha512::new(),
+        }
+    }
+
+    pub fn update(&mut self, chunk: &Chunk) {
+        if chunk.chunk_type() == &self.chunk_type {
+            self.signed.update(chunk.as_bytes());
+        }
+        if self.covers_critical
+            && chunk.chunk_type().is_critical()
+            && chunk.chunk_type() != &ChunkType::IEND
+        {
+            self.critical.update(chunk.as_bytes());
+        }
+    }
+
+    fn flags(&self) -> u8 {
+        if self.covers_critical {
+            FLAG_CRITICAL
+        } else {
+            0
+        }
+    }
+
+    fn finalize(self) -> [u8; 64] {
+        let mut digest = Sha512::new();
+        digest.update(DOMAIN);
+        digest.update([self.flags()]);
+        digest.update(self.chunk_type.bytes());
+        digest.update(self.signed.finalize());
+        if self.covers_critical {
+            digest.update(self.critical.finalize());
+        }
+        digest.finalize().into()
+    }
+
+    /// Signs everything seen so far and returns the signature chunk.
+    pub fn sign(self, signing_key: &[u8; KEY_LEN]) -> Chunk {
+        let mut data = vec![VERSION, self.flags()];
+        data.extend_from_slice(&self.chunk_type.bytes());
+        let digest = self.finalize();
+        let signature = SigningKey::from_bytes(signing_key).sign(&digest);
+        data.extend_from_slice(&signature.to_bytes());
+        Chunk::new(signature_chunk_type(), data)
+    }
+}
+
+/// Generates an Ed25519 key pair, returned as `(secret, public)`.
+pub fn generate_signing_keys() -> ([u8; KEY_LEN], [u8; KEY_LEN]) {
+    let signing_key = SigningKey::generate(&mut OsRng);
+    (
+        signing_key.to_bytes(),
+        signing_key.verifying_key().to_bytes(),
+    )
+}
+
+/// Signs the chunks of `chunk_type` in `chunks` and returns the signature
+/// chunk to store alongside them.
+pub fn sign_chunks(
+    chunks: &[Chunk],
+    chunk_type: &ChunkType,
+    covers_critical: bool,
+    signing_key: &[u8; KEY_LEN],
+) -> Chunk {
+    let mut hasher = SignatureHasher::new(chunk_type.clone(), covers_critical);
+    for chunk in chunks {
+        hasher.update(chunk);
+    }
+    hasher.sign(signing_key)
+}
+
+/// Checks every signature chunk in `chunks` against `verifying_key`, returning
+/// the signed chunk type and the outcome for each one. A signature whose
+/// chunk type no longer appears is reported as missing. An empty list means
+/// the chunks carry no signature at all.
+pub fn verify_chunks(
+    chunks: &[Chunk],
+    verifying_key: &[u8; KEY_LEN],
+) -> Result<Vec<(ChunkType, SignatureStatus)>> {
+    let verifying_key =
+        VerifyingKey::from_bytes(verifying_key).map_err(|_| PngError::InvalidKey {
+            reason: "not an Ed25519 public key".into(),
+        })?;
+    let mut results = Vec::new();
+    for signature_chunk in chunks
+        .iter()
+        .filter(|chunk| chunk.chunk_type() == &signature_chunk_type())
+    {
+        let data = signature_chunk.data();
+        if data.len() != DATA_LEN || data[0] != VERSION {
+            return Err(PngError::InvalidPayload {
+                reason: "signature chunk is malformed".into(),
+            });
+        }
+        let chunk_type = ChunkType::try_from(<[u8; 4]>::try_from(&data[2..6]).unwrap())?;
+        let signature = Signature::from_bytes(data[6..].try_into().unwrap());
+
+        if !chunks.iter().any(|chunk| chunk.chunk_type() == &chunk_type) {
+            results.push((chunk_type, SignatureStatus::Missing));
+            continue;
+        }
+        let mut hasher = SignatureHasher::new(chunk_type.clone(), data[1] & FLAG_CRITICAL != 0);
+        for chunk in chunks {
+            hasher.update(chunk);
+        }
+        let status = match verifying_key.verify(&hasher.finalize(), &signature) {
+            Ok(()) => SignatureStatus::Valid,
+            Err(_) => SignatureStatus::Invalid,
+        };
+        results.push((chunk_type, status));
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(
+            ChunkType::from_str(chunk_type).unwrap(),
+            data.as_bytes().to_vec(),
+        )
+    }
+
+    fn testing_chunks() -> Vec<Chunk> {
+        vec![
+            chunk("IHDR", "header"),
+            chunk("IDAT", "pixels"),
+            chunk("ruSt", "Secret message"),
+            chunk("IEND", ""),
+        ]
+    }
+
+    fn signed_chunks(covers_critical: bool, signing_key: &[u8; KEY_LEN]) -> Vec<Chunk> {
+        let mut chunks = testing_chunks();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let signature = sign_chunks(&chunks, &chunk_type, covers_critical, signing_key);
+        chunks.insert(3, signature);
+        chunks
+    }
+
+    #[test]
+    fn test_valid_signature() {
+        let (secret, public) = generate_signing_keys();
+        for covers_critical in [false, true] {
+            let chunks = signed_chunks(covers_critical, &secret);
+            let results = verify_chunks(&chunks, &public).unwrap();
+            assert_eq!(
+                results,
+                vec![(ChunkType::from_str("ruSt").unwrap(), SignatureStatus::Valid)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_tampered_message() {
+        let (secret, public) = generate_signing_keys();
+        let mut chunks = signed_chunks(false, &secret);
+        chunks[2] = chunk("ruSt", "Forged message");
+        let results = verify_chunks(&chunks, &public).unwrap();
+        assert_eq!(results[0].1, SignatureStatus::Invalid);
+    }
+
+    #[test]
+    fn test_tampered_critical_chunk() {
+        let (secret, public) = generate_signing_keys();
+
+        let mut chunks = signed_chunks(false, &secret);
+        chunks[1] = chunk("IDAT", "other pixels");
+        assert_eq!(
+            verify_chunks(&chunks, &public).unwrap()[0].1,
+            SignatureStatus::Valid
+        );
+
+        let mut chunks = signed_chunks(true, &secret);
+        chunks[1] = chunk("IDAT", "other pixels");
+        assert_eq!(
+            verify_chunks(&chunks, &public).unwrap()[0].1,
+            SignatureStatus::Invalid
+        );
+    }
+
+    #[test]
+    fn test_wrong_key() {
+        let (secret, _) = generate_signing_keys();
+        let (_, other_public) = generate_signing_keys();
+        let chunks = signed_chunks(false, &secret);
+        let results = verify_chunks(&chunks, &other_public).unwrap();
+        assert_eq!(results[0].1, SignatureStatus::Invalid);
+    }
+
+    #[test]
+    fn test_missing_signed_chunk() {
+        let (secret, public) = generate_signing_keys();
+        let mut chunks = signed_chunks(false, &secret);
+        chunks.remove(2);
+        let results = verify_chunks(&chunks, &public).unwrap();
+        assert_eq!(results[0].1, SignatureStatus::Missing);
+    }
+
+    #[test]
+    fn test_unsigned_file() {
+        let (_, public) = generate_signing_keys();
+        assert_eq!(verify_chunks(&testing_chunks(), &public).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_every_signature_is_reported() {
+        let (secret, public) = generate_signing_keys();
+        let mut chunks = signed_chunks(false, &secret);
+        let text = chunk("tEXt", "Title\0Test");
+        let signature = sign_chunks(&[text], &ChunkType::TEXT, false, &secret);
+        chunks.insert(3, signature);
+        chunks[2] = chunk("ruSt", "Forged message");
+        assert_eq!(
+            verify_chunks(&chunks, &public).unwrap(),
+            vec![
+                (ChunkType::TEXT, SignatureStatus::Missing),
+                (
+                    ChunkType::from_str("ruSt").unwrap(),
+                    SignatureStatus::Invalid
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,363 @@
+use sha2::{Digest, Sha256};
+
+use crate::{
+    error::PngError,
+    ihdr::{ColorType, Ihdr},
+    image::{self, ImageData},
+    Result,
+};
+
+// Payloads are hidden in the low bits of pixel samples, most significant bit
+// first, after a 4 byte big-endian length. Pixels are visited in an order
+// shuffled by a generator seeded from the key, and within a pixel the selected
+// channels are used in order.
+const LENGTH_LEN: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StegoOptions {
+    /// Low bits of each selected sample to use, from 1 to 8 but no more than
+    /// the bit depth.
+    pub bits_per_channel: u8,
+    /// Bit `i` selects channel `i`. `None` selects every channel but alpha.
+    pub channel_mask: Option<u8>,
+    /// Seeds the pixel order; the same key is needed to extract.
+    pub key: String,
+}
+
+impl Default for StegoOptions {
+    fn default() -> Self {
+        StegoOptions {
+            bits_per_channel: 1,
+            channel_mask: None,
+            key: String::new(),
+        }
+    }
+}
+
+// SplitMix64, which is small and good enough to shuffle pixels with.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn new(key: &str) -> SplitMix64 {
+        let digest = Sha256::digest(key.as_bytes());
+        SplitMix64(u64::from_be_bytes(digest[..8].try_into().unwrap()))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+// The first `count` entries of a key-seeded shuffle of `0..len`, computed with
+// a partial Fisher-Yates shuffle.
+fn pixel_order(key: &str, len: usize, count: usize) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..len).collect();
+    let mut rng = SplitMix64::new(key);
+    for i in 0..count.min(len) {
+        let j = i + (rng.next() % (len - i) as u64) as usize;
+        order.swap(i, j);
+    }
+    order.truncate(count);
+    order
+}
+
+// The channels to use, checked against the image.
+fn channels(ihdr: &Ihdr, options: &StegoOptions) -> Result<Vec<usize>> {
+    if ihdr.color_type == ColorType::Indexed {
+        return Err(PngError::Unsupported {
+            reason: "indexed images cannot hide data in their samples".into(),
+        });
+    }
+    let max_bits = ihdr.bit_depth.min(8);
+    if options.bits_per_channel == 0 || options.bits_per_channel > max_bits {
+        return Err(PngError::Unsupported {
+            reason: format!(
+                "bits per channel must be between 1 and {} for this image",
+                max_bits
+            ),
+        });
+    }
+    let count = ihdr.color_type.channels();
+    let has_alpha = matches!(ihdr.color_type, ColorType::GrayscaleAlpha | ColorType::Rgba);
+    let mask = options.channel_mask.unwrap_or(match has_alpha {
+        true => (1 << (count - 1)) - 1,
+        false => (1 << count) - 1,
+    });
+    if mask == 0 || mask >> count != 0 {
+        return Err(PngError::Unsupported {
+            reason: format!(
+                "channel mask {:#06b} does not fit an image with {} channels",
+                mask, count
+            ),
+        });
+    }
+    Ok((0..count)
+        .filter(|channel| mask & (1 << channel) != 0)
+        .collect())
+}
+
+/// Number of payload bytes that fit in an image described by `ihdr`.
+pub fn capacity(ihdr: &Ihdr, options: &StegoOptions) -> Result<usize> {
+    let channels = channels(ihdr, options)?;
+    // An image too large to decode cannot hold anything.
+    image::image_sizes(ihdr)?;
+    let bits = ihdr.width as usize
+        * ihdr.height as usize
+        * channels.len()
+        * options.bits_per_channel as usize;
+    Ok((bits / 8).saturating_sub(LENGTH_LEN))
+}
+
+// Byte offsets of the samples to use, in order, enough to hold `bits` bits.
+fn sample_offsets(image: &ImageData, options: &StegoOptions, bits: usize) -> Result<Vec<usize>> {
+    let ihdr = image.ihdr();
+    let channels = channels(ihdr, options)?;
+    let samples = bits.div_ceil(options.bits_per_channel as usize);
+    let pixels = samples.div_ceil(channels.len());
+    let pixel_count = ihdr.width as usize * ihdr.height as usize;
+    let bytes_per_sample = image.bytes_per_sample();
+    let channel_count = ihdr.color_type.channels();
+    Ok(pixel_order(&options.key, pixel_count, pixels)
+        .into_iter()
+        .flat_map(|pixel| {
+            // The low byte of a 16-bit sample is the second one.
+            channels.iter().map(move |channel| {
+                (pixel * channel_count + channel) * bytes_per_sample + bytes_per_sample - 1
+            })
+        })
+        .take(samples)
+        .collect())
+}
+
+/// Hides `payload` in the low bits of the samples of `image`.
+pub fn embed(image: &mut ImageData, payload: &[u8], options: &StegoOptions) -> Result<()> {
+    let available = capacity(image.ihdr(), options)?;
+    if payload.len() > available {
+        return Err(PngError::CapacityExceeded {
+            needed: payload.len(),
+            available,
+        });
+    }
+    let mut message = (payload.len() as u32).to_be_bytes().to_vec();
+    message.extend_from_slice(payload);
+
+    let width = options.bits_per_channel as usize;
+    let offsets = sample_offsets(image, options, message.len() * 8)?;
+    let data = image.data_mut();
+    let mut bits = message
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |bit| (byte >> bit) & 1));
+    for offset in offsets {
+        let mut value = 0;
+        for _ in 0..width {
+            value = (value << 1) | bits.next().unwrap_or(0);
+        }
+        let mask = ((1u16 << width) - 1) as u8;
+        data[offset] = (data[offset] & !mask) | value;
+    }
+    Ok(())
+}
+
+// Reads `len` bytes from the samples at `offsets`.
+fn read_bytes(data: &[u8], offsets: &[usize], width: usize, len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    let bits = offsets
+        .iter()
+        .flat_map(|&offset| (0..width).rev().map(move |bit| (data[offset] >> bit) & 1));
+    for (index, bit) in bits.take(len * 8).enumerate() {
+        bytes[index / 8] |= bit << (7 - index % 8);
+    }
+    bytes
+}
+
+/// Recovers a payload hidden by [`embed`] with the same options.
+pub fn extract(image: &ImageData, options: &StegoOptions) -> Result<Vec<u8>> {
+    let available = capacity(image.ihdr(), options)?;
+    let width = options.bits_per_channel as usize;
+    let offsets = sample_offsets(image, options, LENGTH_LEN * 8)?;
+    let length = read_bytes(image.data(), &offsets, width, LENGTH_LEN);
+    let length = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+    if length > available {
+        return Err(PngError::InvalidPayload {
+            reason: "no hidden payload found; the key or options may be wrong".into(),
+        });
+    }
+    let total = LENGTH_LEN + length;
+    let offsets = sample_offsets(image, options, total * 8)?;
+    let message = read_bytes(image.data(), &offsets, width, total);
+    Ok(message[LENGTH_LEN..].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::InterlaceMethod;
+
+    fn image(color_type: ColorType, bit_depth: u8) -> ImageData {
+        let ihdr = Ihdr {
+            width: 40,
+            height: 30,
+            bit_depth,
+            color_type,
+            compression_method: 0,
+            filter_method: 0,
+            interlace_method: InterlaceMethod::None,
+        };
+        let len = 40 * 30 * color_type.channels() * if bit_depth == 16 { 2 } else { 1 };
+        let mask = if bit_depth < 8 {
+            (1 << bit_depth) - 1
+        } else {
+            0xff
+        };
+        let data = (0..len).map(|i| (i * 37 % 251) as u8 & mask).collect();
+        ImageData::new(ihdr, data).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let cases = [
+            (ColorType::Rgb, 8, 1),
+            (ColorType::Rgba, 8, 2),
+            (ColorType::Grayscale, 16, 8),
+            (ColorType::Grayscale, 2, 2),
+            (ColorType::GrayscaleAlpha, 16, 3),
+        ];
+        for (color_type, bit_depth, bits_per_channel) in cases {
+            let options = StegoOptions {
+                bits_per_channel,
+                key: "hunter2".into(),
+                ..StegoOptions::default()
+            };
+            let mut image = image(color_type, bit_depth);
+            let payload: Vec<u8> = (0..capacity(image.ihdr(), &options).unwrap())
+                .map(|i| (i * 13) as u8)
+                .collect();
+            embed(&mut image, &payload, &options).unwrap();
+            assert_eq!(extract(&image, &options).unwrap(), payload);
+        }
+    }
+
+    #[test]
+    fn test_changes_are_small() {
+        let original = image(ColorType::Rgb, 8);
+        let mut image = original.clone();
+        embed(&mut image, b"Secret message", &StegoOptions::default()).unwrap();
+        let changed = original
+            .data()
+            .iter()
+            .zip(image.data())
+            .filter(|(a, b)| a != b)
+            .count();
+        assert!(changed > 0);
+        assert!(original
+            .data()
+            .iter()
+            .zip(image.data())
+            .all(|(a, b)| a.abs_diff(*b) <= 1));
+    }
+
+    #[test]
+    fn test_alpha_untouched_by_default() {
+        let original = image(ColorType::Rgba, 8);
+        let mut image = original.clone();
+        let options = StegoOptions::default();
+        let payload = vec![0xa5; capacity(image.ihdr(), &options).unwrap()];
+        embed(&mut image, &payload, &options).unwrap();
+        for (a, b) in original.data().chunks(4).zip(image.data().chunks(4)) {
+            assert_eq!(a[3], b[3]);
+        }
+    }
+
+    #[test]
+    fn test_capacity() {
+        let image = image(ColorType::Rgb, 8);
+        let options = StegoOptions::default();
+        assert_eq!(
+            capacity(image.ihdr(), &options).unwrap(),
+            40 * 30 * 3 / 8 - 4
+        );
+        let options = StegoOptions {
+            bits_per_channel: 2,
+            channel_mask: Some(0b001),
+            ..StegoOptions::default()
+        };
+        assert_eq!(
+            capacity(image.ihdr(), &options).unwrap(),
+            40 * 30 * 2 / 8 - 4
+        );
+        let huge = Ihdr {
+            width: 100_000,
+            height: 100_000,
+            ..image.ihdr().clone()
+        };
+        assert!(matches!(
+            capacity(&huge, &options),
+            Err(PngError::InvalidImageData { .. })
+        ));
+
+        let mut image = image;
+        let too_big = vec![0; 40 * 30 * 3 / 8];
+        assert!(matches!(
+            embed(&mut image, &too_big, &StegoOptions::default()),
+            Err(PngError::CapacityExceeded { .. })
+        ));
+    }
+
+    #[test]
+    fn test_key_changes_order() {
+        assert_ne!(pixel_order("a", 1000, 20), pixel_order("b", 1000, 20));
+        assert_eq!(pixel_order("a", 1000, 20), pixel_order("a", 1000, 20));
+        let mut order = pixel_order("a", 100, 100);
+        order.sort();
+        assert_eq!(order, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_wrong_key() {
+        let mut image = image(ColorType::Rgb, 8);
+        let options = StegoOptions {
+            key: "right".into(),
+            ..StegoOptions::default()
+        };
+        embed(&mut image, b"Secret message", &options).unwrap();
+        let wrong = StegoOptions {
+            key: "wrong".into(),
+            ..StegoOptions::default()
+        };
+        assert_ne!(
+            extract(&image, &wrong).ok(),
+            Some(b"Secret message".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_unsupported_options() {
+        let indexed = image(ColorType::Indexed, 8);
+        assert!(capacity(indexed.ihdr(), &StegoOptions::default()).is_err());
+
+        let gray = image(ColorType::Grayscale, 2);
+        for options in [
+            StegoOptions {
+                bits_per_channel: 3,
+                ..StegoOptions::default()
+            },
+            StegoOptions {
+                bits_per_channel: 0,
+                ..StegoOptions::default()
+            },
+            StegoOptions {
+                channel_mask: Some(0b10),
+                ..StegoOptions::default()
+            },
+        ] {
+            assert!(matches!(
+                capacity(gray.ihdr(), &options),
+                Err(PngError::Unsupported { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,382 @@
+use std::fmt::Display;
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    compress::{self, DEFAULT_MAX_OUTPUT_SIZE},
+    error::PngError,
+    Result,
+};
+
+/// A textual chunk as defined by the PNG spec.
+///
+/// tEXt and zTXt hold Latin-1 text, zTXt compressed. iTXt holds UTF-8 text,
+/// optionally compressed, along with a language tag and a translation of the
+/// keyword.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextChunk {
+    Text {
+        keyword: String,
+        text: String,
+    },
+    CompressedText {
+        keyword: String,
+        text: String,
+    },
+    InternationalText {
+        keyword: String,
+        compressed: bool,
+        language_tag: String,
+        translated_keyword: String,
+        text: String,
+    },
+}
+
+fn invalid(reason: String) -> PngError {
+    PngError::InvalidText { reason }
+}
+
+/// Whether chunks of `chunk_type` can be read as a [`TextChunk`].
+pub fn is_text_chunk(chunk_type: &ChunkType) -> bool {
+    [ChunkType::TEXT, ChunkType::ZTXT, ChunkType::ITXT].contains(chunk_type)
+}
+
+fn decode_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| byte as char).collect()
+}
+
+fn encode_latin1(text: &str) -> Result<Vec<u8>> {
+    text.chars()
+        .map(|c| u8::try_from(c).map_err(|_| invalid(format!("{:?} is not Latin-1", c))))
+        .collect()
+}
+
+/// Whether `text` can be stored in a tEXt or zTXt chunk.
+pub fn is_latin1(text: &str) -> bool {
+    text.chars().all(|c| (c as u32) < 0x100)
+}
+
+// Keywords are 1 to 79 printable Latin-1 characters with no leading, trailing
+// or consecutive spaces.
+fn check_keyword(keyword: &str) -> Result<()> {
+    let bytes = encode_latin1(keyword)?;
+    if bytes.is_empty() || bytes.len() > 79 {
+        return Err(invalid(format!(
+            "keyword must be 1 to 79 characters but has {}",
+            bytes.len()
+        )));
+    }
+    if !bytes
+        .iter()
+        .all(|&byte| (32..=126).contains(&byte) || byte >= 161)
+    {
+        return Err(invalid(format!(
+            "keyword {:?} has non-printable characters",
+            keyword
+        )));
+    }
+    if keyword.starts_with(' ') || keyword.ends_with(' ') || keyword.contains("  ") {
+        return Err(invalid(format!(
+            "keyword {:?} has leading, trailing or consecutive spaces",
+            keyword
+        )));
+    }
+    Ok(())
+}
+
+// Splits `data` at the first null byte, dropping the separator.
+fn split_null<'a>(data: &'a [u8], field: &str) -> Result<(&'a [u8], &'a [u8])> {
+    match data.iter().position(|&byte| byte == 0) {
+        Some(index) => Ok((&data[..index], &data[index + 1..])),
+        None => Err(invalid(format!("{} is not null terminated", field))),
+    }
+}
+
+fn inflate_text(data: &[u8]) -> Result<Vec<u8>> {
+    compress::inflate(data, DEFAULT_MAX_OUTPUT_SIZE).map_err(|error| match error {
+        PngError::InvalidPayload { reason } => invalid(reason),
+        error => error,
+    })
+}
+
+fn check_compression_method(method: u8) -> Result<()> {
+    match method {
+        0 => Ok(()),
+        method => Err(invalid(format!("unknown compression method {}", method))),
+    }
+}
+
+impl TextChunk {
+    /// Builds a tEXt chunk if `text` is Latin-1, otherwise an iTXt chunk.
+    pub fn new(keyword: &str, text: &str) -> Result<TextChunk> {
+        check_keyword(keyword)?;
+        Ok(match is_latin1(text) {
+            true => TextChunk::Text {
+                keyword: keyword.into(),
+                text: text.into(),
+            },
+            false => TextChunk::InternationalText {
+                keyword: keyword.into(),
+                compressed: false,
+                language_tag: String::new(),
+                translated_keyword: String::new(),
+                text: text.into(),
+            },
+        })
+    }
+
+    pub fn keyword(&self) -> &str {
+        match self {
+            TextChunk::Text { keyword, .. }
+            | TextChunk::CompressedText { keyword, .. }
+            | TextChunk::InternationalText { keyword, .. } => keyword,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        match self {
+            TextChunk::Text { text, .. }
+            | TextChunk::CompressedText { text, .. }
+            | TextChunk::InternationalText { text, .. } => text,
+        }
+    }
+
+    pub fn chunk_type(&self) -> ChunkType {
+        match self {
+            TextChunk::Text { .. } => ChunkType::TEXT,
+            TextChunk::CompressedText { .. } => ChunkType::ZTXT,
+            TextChunk::InternationalText { .. } => ChunkType::ITXT,
+        }
+    }
+
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        check_keyword(self.keyword())?;
+        let mut data = encode_latin1(self.keyword())?;
+        data.push(0);
+        match self {
+            TextChunk::Text { text, .. } => {
+                if text.contains('\0') {
+                    return Err(invalid("text may not contain null characters".into()));
+                }
+                data.extend_from_slice(&encode_latin1(text)?);
+            }
+            TextChunk::CompressedText { text, .. } => {
+                data.push(0);
+                data = compress::deflate(&encode_latin1(text)?, data)?;
+            }
+            TextChunk::InternationalText {
+                compressed,
+                language_tag,
+                translated_keyword,
+                text,
+                ..
+            } => {
+                if language_tag.contains('\0') || translated_keyword.contains('\0') {
+                    return Err(invalid(
+                        "language tag and translated keyword may not contain null characters"
+                            .into(),
+                    ));
+                }
+                data.extend_from_slice(&[*compressed as u8, 0]);
+                data.extend_from_slice(language_tag.as_bytes());
+                data.push(0);
+                data.extend_from_slice(translated_keyword.as_bytes());
+                data.push(0);
+                data = match compressed {
+                    true => compress::deflate(text.as_bytes(), data)?,
+                    false => {
+                        data.extend_from_slice(text.as_bytes());
+                        data
+                    }
+                };
+            }
+        }
+        Ok(Chunk::new(self.chunk_type(), data))
+    }
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+    type Error = crate::Error;
+    fn try_from(value: &Chunk) -> Result<Self> {
+        let chunk_type = value.chunk_type();
+        if !is_text_chunk(chunk_type) {
+            return Err(invalid(format!("{} is not a text chunk", chunk_type)));
+        }
+        let (keyword, rest) = split_null(value.data(), "keyword")?;
+        let keyword = decode_latin1(keyword);
+        check_keyword(&keyword)?;
+
+        if chunk_type == &ChunkType::TEXT {
+            return Ok(TextChunk::Text {
+                keyword,
+                text: decode_latin1(rest),
+            });
+        }
+        if chunk_type == &ChunkType::ZTXT {
+            let (&method, compressed) = rest
+                .split_first()
+                .ok_or_else(|| invalid("compression method is missing".into()))?;
+            check_compression_method(method)?;
+            return Ok(TextChunk::CompressedText {
+                keyword,
+                text: decode_latin1(&inflate_text(compressed)?),
+            });
+        }
+
+        if rest.len() < 2 {
+            return Err(invalid("compression flag and method are missing".into()));
+        }
+        let compressed = match rest[0] {
+            0 => false,
+            1 => true,
+            flag => return Err(invalid(format!("unknown compression flag {}", flag))),
+        };
+        check_compression_method(rest[1])?;
+        let (language_tag, rest) = split_null(&rest[2..], "language tag")?;
+        let (translated_keyword, text) = split_null(rest, "translated keyword")?;
+        let text = match compressed {
+            true => inflate_text(text)?,
+            false => text.to_vec(),
+        };
+        let utf8 = |bytes: Vec<u8>, field: &str| {
+            String::from_utf8(bytes).map_err(|_| invalid(format!("{} is not valid UTF-8", field)))
+        };
+        Ok(TextChunk::InternationalText {
+            keyword,
+            compressed,
+            language_tag: utf8(language_tag.to_vec(), "language tag")?,
+            translated_keyword: utf8(translated_keyword.to_vec(), "translated keyword")?,
+            text: utf8(text, "text")?,
+        })
+    }
+}
+
+impl Display for TextChunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextChunk::InternationalText {
+                keyword,
+                language_tag,
+                text,
+                ..
+            } if !language_tag.is_empty() => {
+                write!(f, "{} [{}]: {}", keyword, language_tag, text)
+            }
+            text_chunk => write!(f, "{}: {}", text_chunk.keyword(), text_chunk.text()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(text_chunk: TextChunk) {
+        let chunk = text_chunk.to_chunk().unwrap();
+        assert_eq!(chunk.chunk_type(), &text_chunk.chunk_type());
+        assert_eq!(TextChunk::try_from(&chunk).unwrap(), text_chunk);
+    }
+
+    #[test]
+    fn test_text_layout() {
+        let chunk = TextChunk::new("Title", "Caf\u{e9}")
+            .unwrap()
+            .to_chunk()
+            .unwrap();
+        assert_eq!(chunk.chunk_type(), &ChunkType::TEXT);
+        assert_eq!(chunk.data(), b"Title\0Caf\xe9");
+    }
+
+    #[test]
+    fn test_round_trips() {
+        round_trip(TextChunk::Text {
+            keyword: "Author".into(),
+            text: "Jane Doe".into(),
+        });
+        round_trip(TextChunk::CompressedText {
+            keyword: "Comment".into(),
+            text: "A long comment. ".repeat(50),
+        });
+        for compressed in [false, true] {
+            round_trip(TextChunk::InternationalText {
+                keyword: "Title".into(),
+                compressed,
+                language_tag: "ja".into(),
+                translated_keyword: "\u{30bf}\u{30a4}\u{30c8}\u{30eb}".into(),
+                text: "\u{3053}\u{3093}\u{306b}\u{3061}\u{306f}".into(),
+            });
+        }
+    }
+
+    #[test]
+    fn test_new_picks_chunk_type() {
+        assert_eq!(
+            TextChunk::new("Title", "plain").unwrap().chunk_type(),
+            ChunkType::TEXT
+        );
+        assert_eq!(
+            TextChunk::new("Title", "\u{1f980}").unwrap().chunk_type(),
+            ChunkType::ITXT
+        );
+    }
+
+    #[test]
+    fn test_invalid_keywords() {
+        for keyword in [
+            "",
+            " Title",
+            "Title ",
+            "Two  spaces",
+            "Tab\there",
+            "\u{1f980}",
+        ] {
+            assert!(
+                matches!(
+                    TextChunk::new(keyword, "text"),
+                    Err(PngError::InvalidText { .. })
+                ),
+                "{:?}",
+                keyword
+            );
+        }
+        assert!(TextChunk::new(&"k".repeat(80), "text").is_err());
+        assert!(TextChunk::new(&"k".repeat(79), "text").is_ok());
+    }
+
+    #[test]
+    fn test_malformed_chunks() {
+        let malformed = [
+            (ChunkType::TEXT, b"no terminator".to_vec()),
+            (ChunkType::ZTXT, b"Comment\0\x00not zlib".to_vec()),
+            (ChunkType::ZTXT, b"Comment\0\x01".to_vec()),
+            (ChunkType::ITXT, b"Title\0\x02\x00\0\0text".to_vec()),
+            (ChunkType::ITXT, b"Title\0\x00\x00en".to_vec()),
+            (ChunkType::ITXT, b"Title\0\x00\x00\0\0\xff".to_vec()),
+        ];
+        for (chunk_type, data) in malformed {
+            let chunk = Chunk::new(chunk_type, data);
+            assert!(
+                matches!(
+                    TextChunk::try_from(&chunk),
+                    Err(PngError::InvalidText { .. })
+                ),
+                "{:?}",
+                chunk
+            );
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let text = TextChunk::new("Title", "Dice").unwrap();
+        assert_eq!(text.to_string(), "Title: Dice");
+        let text = TextChunk::InternationalText {
+            keyword: "Title".into(),
+            compressed: false,
+            language_tag: "de".into(),
+            translated_keyword: "Titel".into(),
+            text: "W\u{fc}rfel".into(),
+        };
+        assert_eq!(text.to_string(), "Title [de]: W\u{fc}rfel");
+    }
+}
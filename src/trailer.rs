@@ -0,0 +1,45 @@
+// Formats commonly appended to PNG files to make polyglots, by the bytes
+// they start with.
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"PK\x03\x04", "ZIP archive"),
+    (b"PK\x05\x06", "ZIP archive (empty)"),
+    (b"Rar!\x1a\x07", "RAR archive"),
+    (b"7z\xbc\xaf\x27\x1c", "7-Zip archive"),
+    (b"\x1f\x8b", "gzip data"),
+    (b"%PDF-", "PDF document"),
+    (b"\x89PNG\r\n\x1a\n", "PNG image"),
+    (b"\xff\xd8\xff", "JPEG image"),
+    (b"GIF8", "GIF image"),
+    (b"\x7fELF", "ELF executable"),
+    (b"MZ", "DOS/Windows executable"),
+];
+
+/// Guesses what the data appended after IEND is from its first bytes.
+pub fn identify(trailer: &[u8]) -> Option<&'static str> {
+    if let Some((_, name)) = SIGNATURES
+        .iter()
+        .find(|(magic, _)| trailer.starts_with(magic))
+    {
+        return Some(name);
+    }
+    // ZIP archives are read from their end, so one may be appended after
+    // other junk and still open.
+    if trailer.windows(4).any(|window| window == b"PK\x05\x06") {
+        return Some("ZIP archive");
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identify() {
+        assert_eq!(identify(b"PK\x03\x04rest"), Some("ZIP archive"));
+        assert_eq!(identify(b"%PDF-1.7\n"), Some("PDF document"));
+        assert_eq!(identify(b"junk PK\x05\x06\0\0\0\0"), Some("ZIP archive"));
+        assert_eq!(identify(b"just some text"), None);
+        assert_eq!(identify(b""), None);
+    }
+}
@@ -0,0 +1,444 @@
+use std::fmt::Display;
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    error::PngError,
+    ihdr::{ColorType, Ihdr},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    MissingChunk {
+        chunk_type: ChunkType,
+    },
+    DuplicateChunk {
+        index: usize,
+        chunk_type: ChunkType,
+    },
+    MisplacedChunk {
+        index: usize,
+        chunk_type: ChunkType,
+        rule: &'static str,
+    },
+    NonContiguousIdat {
+        index: usize,
+    },
+    ChunkAfterIend {
+        index: usize,
+        chunk_type: ChunkType,
+    },
+    /// Bytes after IEND that are not chunks.
+    DataAfterIend {
+        length: usize,
+    },
+    UnknownCriticalChunk {
+        index: usize,
+        chunk_type: ChunkType,
+    },
+    ReservedBitSet {
+        index: usize,
+        chunk_type: ChunkType,
+    },
+    InvalidHeader {
+        reason: String,
+    },
+    /// PLTE in an image whose color type does not allow one.
+    UnexpectedPalette {
+        index: usize,
+        color_type: ColorType,
+    },
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::MissingChunk { chunk_type } => {
+                write!(f, "Required chunk {} is missing", chunk_type)
+            }
+            Violation::DuplicateChunk { index, chunk_type } => write!(
+                f,
+                "Chunk {} at index {} may only appear once",
+                chunk_type, index
+            ),
+            Violation::MisplacedChunk {
+                index,
+                chunk_type,
+                rule,
+            } => write!(f, "Chunk {} at index {} {}", chunk_type, index, rule),
+            Violation::NonContiguousIdat { index } => write!(
+                f,
+                "IDAT chunk at index {} is separated from the previous IDAT chunks",
+                index
+            ),
+            Violation::ChunkAfterIend { index, chunk_type } => {
+                write!(
+                    f,
+                    "Chunk {} at index {} comes after IEND",
+                    chunk_type, index
+                )
+            }
+            Violation::DataAfterIend { length } => {
+                write!(f, "{} bytes of data come after IEND", length)
+            }
+            Violation::UnknownCriticalChunk { index, chunk_type } => write!(
+                f,
+                "Chunk {} at index {} is critical but not known",
+                chunk_type, index
+            ),
+            Violation::ReservedBitSet { index, chunk_type } => write!(
+                f,
+                "Chunk {} at index {} has the reserved bit set",
+                chunk_type, index
+            ),
+            Violation::InvalidHeader { reason } => write!(f, "IHDR is invalid: {}", reason),
+            Violation::UnexpectedPalette { index, color_type } => write!(
+                f,
+                "Chunk PLTE at index {} is not allowed in {} images",
+                index, color_type
+            ),
+        }
+    }
+}
+
+const KNOWN_CRITICAL: [&[u8; 4]; 4] = [b"IHDR", b"PLTE", b"IDAT", b"IEND"];
+
+const SINGLE_CHUNKS: [&[u8; 4]; 13] = [
+    b"IHDR", b"PLTE", b"IEND", b"cHRM", b"gAMA", b"iCCP", b"sBIT", b"sRGB", b"bKGD", b"hIST",
+    b"tRNS", b"pHYs", b"tIME",
+];
+
+// Ancillary chunks that must come before PLTE (and therefore IDAT).
+const BEFORE_PLTE: [&[u8; 4]; 5] = [b"cHRM", b"gAMA", b"iCCP", b"sBIT", b"sRGB"];
+
+// Ancillary chunks that must come after PLTE but before IDAT.
+const AFTER_PLTE: [&[u8; 4]; 3] = [b"bKGD", b"hIST", b"tRNS"];
+
+// Ancillary chunks that only need to come before IDAT.
+const BEFORE_IDAT: [&[u8; 4]; 2] = [b"pHYs", b"sPLT"];
+
+fn is_one_of(chunk_type: &ChunkType, types: &[&[u8; 4]]) -> bool {
+    types.iter().any(|bytes| chunk_type.bytes() == **bytes)
+}
+
+pub fn validate(chunks: &[Chunk]) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let types: Vec<&ChunkType> = chunks.iter().map(|chunk| chunk.chunk_type()).collect();
+    let first = |chunk_type: &ChunkType| types.iter().position(|t| *t == chunk_type);
+
+    for chunk_type in [ChunkType::IHDR, ChunkType::IDAT, ChunkType::IEND] {
+        if first(&chunk_type).is_none() {
+            violations.push(Violation::MissingChunk { chunk_type });
+        }
+    }
+
+    let first_plte = first(&ChunkType::PLTE);
+    if let Some(ihdr) = chunks.iter().find(|c| c.chunk_type() == &ChunkType::IHDR) {
+        match Ihdr::try_from(ihdr) {
+            Ok(ihdr) => match (ihdr.color_type, first_plte) {
+                (ColorType::Indexed, None) => violations.push(Violation::MissingChunk {
+                    chunk_type: ChunkType::PLTE,
+                }),
+                (color_type @ (ColorType::Grayscale | ColorType::GrayscaleAlpha), Some(index)) => {
+                    violations.push(Violation::UnexpectedPalette { index, color_type })
+                }
+                _ => {}
+            },
+            Err(PngError::InvalidIhdr { reason }) => {
+                violations.push(Violation::InvalidHeader { reason })
+            }
+            Err(_) => {}
+        }
+    }
+
+    let first_idat = first(&ChunkType::IDAT);
+    let first_iend = first(&ChunkType::IEND);
+
+    for (index, chunk_type) in types.iter().enumerate() {
+        let chunk_type = (*chunk_type).clone();
+
+        if !chunk_type.is_reserved_bit_valid() {
+            violations.push(Violation::ReservedBitSet {
+                index,
+                chunk_type: chunk_type.clone(),
+            });
+        }
+        if chunk_type.is_critical() && !is_one_of(&chunk_type, &KNOWN_CRITICAL) {
+            violations.push(Violation::UnknownCriticalChunk {
+                index,
+                chunk_type: chunk_type.clone(),
+            });
+        }
+        if is_one_of(&chunk_type, &SINGLE_CHUNKS) && first(&chunk_type) != Some(index) {
+            violations.push(Violation::DuplicateChunk {
+                index,
+                chunk_type: chunk_type.clone(),
+            });
+        }
+        if matches!(first_iend, Some(iend) if index > iend) {
+            violations.push(Violation::ChunkAfterIend {
+                index,
+                chunk_type: chunk_type.clone(),
+            });
+        }
+
+        let is_after = |other: Option<usize>| matches!(other, Some(other) if index > other);
+        let is_before = |other: Option<usize>| matches!(other, Some(other) if index < other);
+        let rule = if chunk_type == ChunkType::IHDR && index != 0 {
+            Some("must be the first chunk")
+        } else if chunk_type != ChunkType::IHDR && index == 0 {
+            Some("comes before IHDR")
+        } else if chunk_type == ChunkType::IEND && index + 1 != chunks.len() {
+            Some("must be the last chunk")
+        } else if chunk_type == ChunkType::PLTE && is_after(first_idat) {
+            Some("must come before IDAT")
+        } else if is_one_of(&chunk_type, &BEFORE_PLTE)
+            && (is_after(first_plte) || is_after(first_idat))
+        {
+            Some("must come before PLTE and IDAT")
+        } else if is_one_of(&chunk_type, &AFTER_PLTE) && is_before(first_plte) {
+            Some("must come after PLTE")
+        } else if (is_one_of(&chunk_type, &AFTER_PLTE) || is_one_of(&chunk_type, &BEFORE_IDAT))
+            && is_after(first_idat)
+        {
+            Some("must come before IDAT")
+        } else {
+            None
+        };
+        if let Some(rule) = rule {
+            violations.push(Violation::MisplacedChunk {
+                index,
+                chunk_type: chunk_type.clone(),
+                rule,
+            });
+        }
+
+        if chunk_type == ChunkType::IDAT
+            && index > 0
+            && first_idat != Some(index)
+            && types[index - 1] != &ChunkType::IDAT
+        {
+            violations.push(Violation::NonContiguousIdat { index });
+        }
+    }
+
+    violations
+}
+
+/// Checks the bytes after IEND, which a valid file does not have. Chunks
+/// there are reported one by one, numbered on from the `chunk_count` chunks
+/// before them, and whatever follows the last of them as data.
+pub fn validate_trailer(chunk_count: usize, trailer: &[u8]) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let mut rest = trailer;
+    while !rest.is_empty() {
+        let chunk = rest
+            .get(..4)
+            .and_then(|length| {
+                12usize.checked_add(u32::from_be_bytes(length.try_into().unwrap()) as usize)
+            })
+            .and_then(|end| Some((end, Chunk::try_from(rest.get(..end)?).ok()?)));
+        let Some((end, chunk)) = chunk else {
+            violations.push(Violation::DataAfterIend { length: rest.len() });
+            break;
+        };
+        violations.push(Violation::ChunkAfterIend {
+            index: chunk_count + violations.len(),
+            chunk_type: chunk.chunk_type().clone(),
+        });
+        rest = &rest[end..];
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    // 1x1 RGB image, so PLTE is allowed but not required
+    const IHDR_DATA: [u8; 13] = [0, 0, 0, 1, 0, 0, 0, 1, 8, 2, 0, 0, 0];
+
+    fn chunks(types: &[&str]) -> Vec<Chunk> {
+        types
+            .iter()
+            .map(|t| {
+                let chunk_type = ChunkType::from_str(t).unwrap();
+                let data = match chunk_type == ChunkType::IHDR {
+                    true => IHDR_DATA.to_vec(),
+                    false => vec![],
+                };
+                Chunk::new(chunk_type, data)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_png() {
+        let chunks = chunks(&[
+            "IHDR", "gAMA", "PLTE", "tRNS", "IDAT", "IDAT", "tEXt", "IEND",
+        ]);
+        assert_eq!(validate(&chunks), vec![]);
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let mut chunks = chunks(&["IHDR", "IDAT", "IEND"]);
+        chunks[0] = Chunk::new(ChunkType::IHDR, vec![]);
+        let violations = validate(&chunks);
+        assert_eq!(
+            violations,
+            vec![Violation::InvalidHeader {
+                reason: "expected 13 bytes of data but found 0".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_missing_chunks() {
+        let chunks = chunks(&["IHDR"]);
+        assert_eq!(
+            validate(&chunks),
+            vec![
+                Violation::MissingChunk {
+                    chunk_type: ChunkType::IDAT
+                },
+                Violation::MissingChunk {
+                    chunk_type: ChunkType::IEND
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_ihdr() {
+        let chunks = chunks(&["IHDR", "IHDR", "IDAT", "IEND"]);
+        let violations = validate(&chunks);
+        assert!(violations.contains(&Violation::DuplicateChunk {
+            index: 1,
+            chunk_type: ChunkType::IHDR
+        }));
+        assert!(violations.contains(&Violation::MisplacedChunk {
+            index: 1,
+            chunk_type: ChunkType::IHDR,
+            rule: "must be the first chunk"
+        }));
+    }
+
+    #[test]
+    fn test_non_contiguous_idat() {
+        let chunks = chunks(&["IHDR", "IDAT", "tEXt", "IDAT", "IEND"]);
+        assert_eq!(
+            validate(&chunks),
+            vec![Violation::NonContiguousIdat { index: 3 }]
+        );
+    }
+
+    #[test]
+    fn test_plte_after_idat() {
+        let chunks = chunks(&["IHDR", "IDAT", "PLTE", "IEND"]);
+        assert_eq!(
+            validate(&chunks),
+            vec![Violation::MisplacedChunk {
+                index: 2,
+                chunk_type: ChunkType::PLTE,
+                rule: "must come before IDAT"
+            }]
+        );
+    }
+
+    #[test]
+    fn test_ancillary_ordering() {
+        let chunks = chunks(&["IHDR", "PLTE", "gAMA", "IDAT", "pHYs", "IEND"]);
+        let violations = validate(&chunks);
+        assert_eq!(
+            violations,
+            vec![
+                Violation::MisplacedChunk {
+                    index: 2,
+                    chunk_type: ChunkType::from_str("gAMA").unwrap(),
+                    rule: "must come before PLTE and IDAT"
+                },
+                Violation::MisplacedChunk {
+                    index: 4,
+                    chunk_type: ChunkType::from_str("pHYs").unwrap(),
+                    rule: "must come before IDAT"
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_chunk_after_iend() {
+        let chunks = chunks(&["IHDR", "IDAT", "IEND", "ruSt"]);
+        assert_eq!(
+            validate(&chunks),
+            vec![
+                Violation::MisplacedChunk {
+                    index: 2,
+                    chunk_type: ChunkType::IEND,
+                    rule: "must be the last chunk"
+                },
+                Violation::ChunkAfterIend {
+                    index: 3,
+                    chunk_type: ChunkType::from_str("ruSt").unwrap()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_critical_and_reserved_bit() {
+        let chunks = chunks(&["IHDR", "RUst", "IDAT", "IEND"]);
+        let violations = validate(&chunks);
+        let rust = ChunkType::from_str("RUst").unwrap();
+        assert!(violations.contains(&Violation::UnknownCriticalChunk {
+            index: 1,
+            chunk_type: rust.clone()
+        }));
+        assert!(violations.contains(&Violation::ReservedBitSet {
+            index: 1,
+            chunk_type: rust
+        }));
+    }
+
+    #[test]
+    fn test_palette_and_color_type() {
+        let mut indexed = chunks(&["IHDR", "IDAT", "IEND"]);
+        indexed[0] = Chunk::new(ChunkType::IHDR, vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 3, 0, 0, 0]);
+        assert_eq!(
+            validate(&indexed),
+            vec![Violation::MissingChunk {
+                chunk_type: ChunkType::PLTE
+            }]
+        );
+
+        let mut grayscale = chunks(&["IHDR", "PLTE", "IDAT", "IEND"]);
+        grayscale[0] = Chunk::new(ChunkType::IHDR, vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 4, 0, 0, 0]);
+        assert_eq!(
+            validate(&grayscale),
+            vec![Violation::UnexpectedPalette {
+                index: 1,
+                color_type: ColorType::GrayscaleAlpha
+            }]
+        );
+    }
+
+    #[test]
+    fn test_trailer() {
+        assert_eq!(validate_trailer(3, &[]), vec![]);
+        let mut trailer =
+            Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hi".to_vec()).as_bytes();
+        trailer.extend_from_slice(b"PK\x03\x04");
+        assert_eq!(
+            validate_trailer(3, &trailer),
+            vec![
+                Violation::ChunkAfterIend {
+                    index: 3,
+                    chunk_type: ChunkType::from_str("ruSt").unwrap()
+                },
+                Violation::DataAfterIend { length: 4 },
+            ]
+        );
+    }
+}
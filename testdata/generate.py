@@ -0,0 +1,111 @@
+#!/usr/bin/env python3
+"""Writes the reference images used by the image decoding tests.
+
+Every legal color type and bit depth is written both plain and Adam7
+interlaced. Rows cycle through the five filter types so each one is
+exercised. Sample values follow `sample()`, which the tests recompute.
+"""
+
+import struct
+import zlib
+from pathlib import Path
+
+CHANNELS = {0: 1, 2: 3, 3: 1, 4: 2, 6: 4}
+BIT_DEPTHS = {0: [1, 2, 4, 8, 16], 2: [8, 16], 3: [1, 2, 4, 8], 4: [8, 16], 6: [8, 16]}
+ADAM7 = [(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)]
+
+
+def sample(x, y, channel, bit_depth):
+    if bit_depth == 16:
+        return (x * 1031 + y * 4099 + channel * 7433) % 65536
+    return (x * 7 + y * 13 + channel * 29 + 3) % (1 << bit_depth)
+
+
+def chunk(chunk_type, data):
+    body = chunk_type + data
+    return struct.pack(">I", len(data)) + body + struct.pack(">I", zlib.crc32(body))
+
+
+def pack_row(xs, y, color_type, bit_depth):
+    samples = [sample(x, y, c, bit_depth) for x in xs for c in range(CHANNELS[color_type])]
+    if bit_depth == 16:
+        return b"".join(struct.pack(">H", s) for s in samples)
+    if bit_depth == 8:
+        return bytes(samples)
+    row = bytearray()
+    per_byte = 8 // bit_depth
+    for i in range(0, len(samples), per_byte):
+        byte = 0
+        group = samples[i : i + per_byte]
+        for j, s in enumerate(group):
+            byte |= s << (8 - bit_depth * (j + 1))
+        row.append(byte)
+    return bytes(row)
+
+
+def paeth(a, b, c):
+    p = a + b - c
+    pa, pb, pc = abs(p - a), abs(p - b), abs(p - c)
+    if pa <= pb and pa <= pc:
+        return a
+    return b if pb <= pc else c
+
+
+def filter_row(filter_type, row, previous, bpp):
+    out = bytearray([filter_type])
+    for i, x in enumerate(row):
+        a = row[i - bpp] if i >= bpp else 0
+        b = previous[i] if previous else 0
+        c = previous[i - bpp] if previous and i >= bpp else 0
+        predictor = [0, a, b, (a + b) // 2, paeth(a, b, c)][filter_type]
+        out.append((x - predictor) % 256)
+    return bytes(out)
+
+
+def image_data(width, height, color_type, bit_depth, interlaced):
+    bpp = max(1, CHANNELS[color_type] * bit_depth // 8)
+    passes = ADAM7 if interlaced else [(0, 0, 1, 1)]
+    raw = bytearray()
+    for x0, y0, dx, dy in passes:
+        xs = list(range(x0, width, dx))
+        ys = list(range(y0, height, dy))
+        if not xs or not ys:
+            continue
+        previous = None
+        for index, y in enumerate(ys):
+            row = pack_row(xs, y, color_type, bit_depth)
+            raw += filter_row(index % 5, row, previous, bpp)
+            previous = row
+    return zlib.compress(bytes(raw), 9)
+
+
+def write_image(path, width, height, color_type, bit_depth, interlaced):
+    ihdr = struct.pack(">IIBBBBB", width, height, bit_depth, color_type, 0, 0, int(interlaced))
+    chunks = [chunk(b"IHDR", ihdr)]
+    if color_type == 3:
+        palette = bytes(i % 256 for i in range(3 * (1 << bit_depth)))
+        chunks.append(chunk(b"PLTE", palette))
+    data = image_data(width, height, color_type, bit_depth, interlaced)
+    # Split the stream over several IDAT chunks to exercise concatenation.
+    for start in range(0, len(data), 64):
+        chunks.append(chunk(b"IDAT", data[start : start + 64]))
+    chunks.append(chunk(b"IEND", b""))
+    path.write_bytes(b"\x89PNG\r\n\x1a\n" + b"".join(chunks))
+
+
+def main():
+    directory = Path(__file__).parent / "images"
+    directory.mkdir(exist_ok=True)
+    for color_type, bit_depths in BIT_DEPTHS.items():
+        for bit_depth in bit_depths:
+            for interlaced in (False, True):
+                name = f"c{color_type}d{bit_depth}{'i' if interlaced else 'n'}_13x11.png"
+                write_image(directory / name, 13, 11, color_type, bit_depth, interlaced)
+    # Small interlaced images leave some Adam7 passes empty.
+    for width, height in [(1, 1), (3, 2), (5, 1)]:
+        name = f"c0d1i_{width}x{height}.png"
+        write_image(directory / name, width, height, 0, 1, True)
+
+
+if __name__ == "__main__":
+    main()